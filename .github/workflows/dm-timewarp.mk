@@ -7,10 +7,11 @@
 DM_TIMEWARP_VERSION = <SHA>
 DM_TIMEWARP_SITE = https://github.com/davemollen/dm-TimeWarp.git
 DM_TIMEWARP_SITE_METHOD = git
-DM_TIMEWARP_BUNDLES = dm-TimeWarp.lv2
+DM_TIMEWARP_BUNDLES = dm-TimeWarp.lv2 dm-TimeWarp-mono.lv2
 
 define DM_TIMEWARP_BUILD_CMDS
 	rm -f $(@D)/lv2/dm-TimeWarp.lv2/libdm_time_warp.so
+	rm -f $(@D)/lv2/dm-TimeWarp-mono.lv2/libdm_time_warp.so
 	(cd $(@D)/lv2 && \
 		~/.cargo/bin/cargo build $(MOD_PLUGIN_BUILDER_RUST_BUILD_FLAGS))
 endef
@@ -18,7 +19,9 @@ endef
 define DM_TIMEWARP_INSTALL_TARGET_CMDS
 	$(INSTALL) -d $(TARGET_DIR)/usr/lib/lv2
 	cp -rv $(@D)/lv2/dm-TimeWarp.lv2 $(TARGET_DIR)/usr/lib/lv2/
+	cp -rv $(@D)/lv2/dm-TimeWarp-mono.lv2 $(TARGET_DIR)/usr/lib/lv2/
 	$(INSTALL) -m 644 $(@D)/lv2/target/$(MOD_PLUGIN_BUILDER_RUST_TARGET)/release/libdm_time_warp.so $(TARGET_DIR)/usr/lib/lv2/dm-TimeWarp.lv2/
+	$(INSTALL) -m 644 $(@D)/lv2/target/$(MOD_PLUGIN_BUILDER_RUST_TARGET)/release/libdm_time_warp.so $(TARGET_DIR)/usr/lib/lv2/dm-TimeWarp-mono.lv2/
 endef
 
 $(eval $(generic-package))
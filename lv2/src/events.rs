@@ -5,23 +5,39 @@ use {
 };
 
 impl DmTimeWarp {
-  pub fn handle_events(&mut self, ports: &mut Ports, features: &mut AudioFeatures) {
-    let control_sequence = match ports
+  pub fn handle_events(
+    &mut self,
+    ports: &mut Ports,
+    features: &mut AudioFeatures,
+    sample_count: u32,
+  ) {
+    let sample_count = sample_count as usize;
+    let mut offset = 0;
+
+    if let Some(control_sequence) = ports
       .control
       .read(self.urids.atom.sequence, self.urids.unit.beat)
     {
-      Some(sequence_iter) => sequence_iter,
-      None => return,
-    };
+      for (time_stamp, atom) in control_sequence {
+        self.time_stamp = time_stamp.as_frames().unwrap_or(0);
+        // process the audio up to this event, so the event is applied at its sample offset
+        let frame = (self.time_stamp.max(0) as usize).clamp(offset, sample_count);
+        if frame > offset {
+          self.process_segment(ports, features, offset, frame);
+          offset = frame;
+        }
 
-    for (time_stamp, atom) in control_sequence {
-      self.time_stamp = time_stamp.as_frames().unwrap_or(0);
-      self.read_patch_get_events(atom, ports);
-      self.read_patch_set_events(atom, features);
-      if self.params.midi_enabled {
-        self.read_midi_events(atom);
+        self.read_patch_get_events(atom, ports);
+        self.read_patch_set_events(atom, features);
+        if self.params.midi_enabled {
+          self.read_midi_events(atom);
+        }
       }
     }
+
+    if sample_count > offset {
+      self.process_segment(ports, features, offset, sample_count);
+    }
   }
 
   pub fn write_set_file(&mut self, ports: &mut Ports) {
@@ -33,6 +49,31 @@ impl DmTimeWarp {
       None => return,
     };
 
+    {
+      let mut object_writer = notify_sequence
+        .init(
+          TimeStamp::Frames(self.time_stamp),
+          self.urids.atom.object,
+          ObjectHeader {
+            id: None,
+            otype: self.urids.patch.set_class.into_general(),
+          },
+        )
+        .unwrap();
+      object_writer
+        .init(
+          self.urids.patch.property,
+          self.urids.atom.urid,
+          self.urids.sample.into_general(),
+        )
+        .unwrap();
+      let mut path_value_writer = object_writer
+        .init(self.urids.patch.value, self.urids.atom.path, ())
+        .unwrap();
+      path_value_writer.append(&self.file_path).unwrap();
+    }
+
+    // the status follows the file, so the UI can show why it didn't load
     let mut object_writer = notify_sequence
       .init(
         TimeStamp::Frames(self.time_stamp),
@@ -47,13 +88,13 @@ impl DmTimeWarp {
       .init(
         self.urids.patch.property,
         self.urids.atom.urid,
-        self.urids.sample.into_general(),
+        self.urids.status.into_general(),
       )
       .unwrap();
-    let mut path_value_writer = object_writer
-      .init(self.urids.patch.value, self.urids.atom.path, ())
+    let mut status_value_writer = object_writer
+      .init(self.urids.patch.value, self.urids.atom.string, ())
       .unwrap();
-    path_value_writer.append(&self.file_path).unwrap();
+    status_value_writer.append(&self.file_status).unwrap();
   }
 
   fn read_patch_set_events(
@@ -61,7 +102,7 @@ impl DmTimeWarp {
     atom: UnidentifiedAtom<'static>,
     features: &mut AudioFeatures,
   ) {
-    let mut should_read_patch_value = false;
+    let mut patch_property = None;
 
     let (object_header, object_reader) = match atom.read(self.urids.atom.object, ()) {
       Some(object) => object,
@@ -71,26 +112,45 @@ impl DmTimeWarp {
     if object_header.otype == self.urids.patch.set_class {
       for (property_header, property) in object_reader {
         if property_header.key == self.urids.patch.property {
-          should_read_patch_value = property
+          patch_property = property
             .read(self.urids.atom.urid, ())
-            .map(|patch_property| self.urids.sample.get() == patch_property.get())
-            .unwrap();
+            .map(|patch_property| patch_property.get());
+        }
+
+        if property_header.key != self.urids.patch.value {
+          continue;
         }
 
-        if should_read_patch_value && property_header.key == self.urids.patch.value {
-          self.file_path = property
-            .read(self.urids.atom.path, ())
-            .map(|path| path.to_string())
-            .unwrap();
-
-          features
-            .schedule
-            .schedule_work(WorkRequest::LoadFile(
-              self.file_path.to_string(),
-              self.sample_rate,
-              self.time_warp.get_delay_line_size(),
-            ))
-            .ok();
+        match patch_property {
+          Some(urid) if urid == self.urids.sample.get() => {
+            self.file_path = property
+              .read(self.urids.atom.path, ())
+              .map(|path| path.to_string())
+              .unwrap();
+            // a new file is read from its path, rather than the embedded sample of the last one
+            self.embedded_sample = None;
+
+            features
+              .schedule
+              .schedule_work(self.get_load_file_request())
+              .ok();
+          }
+          Some(urid) if urid == self.urids.record.get() => {
+            if let Some(value) = property.read(self.urids.atom.bool, ()) {
+              self.record.set(value);
+            }
+          }
+          Some(urid) if urid == self.urids.play.get() => {
+            if let Some(value) = property.read(self.urids.atom.bool, ()) {
+              self.play.set(value);
+            }
+          }
+          Some(urid) if urid == self.urids.erase.get() => {
+            if let Some(value) = property.read(self.urids.atom.bool, ()) {
+              self.erase.set(value);
+            }
+          }
+          _ => (),
         }
       }
     };
@@ -0,0 +1,27 @@
+/// Holds a footswitch value that can be set by both its control port and timestamped patch events.
+/// A change of the control port value takes precedence over the last event value.
+pub struct Footswitch {
+  value: bool,
+  prev_port_value: bool,
+}
+
+impl Footswitch {
+  pub fn new(value: bool) -> Self {
+    Self {
+      value,
+      prev_port_value: value,
+    }
+  }
+
+  pub fn set(&mut self, value: bool) {
+    self.value = value;
+  }
+
+  pub fn process(&mut self, port_value: bool) -> bool {
+    if port_value != self.prev_port_value {
+      self.value = port_value;
+      self.prev_port_value = port_value;
+    }
+    self.value
+  }
+}
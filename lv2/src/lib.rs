@@ -1,11 +1,23 @@
 mod events;
+mod footswitch;
+mod mono;
 mod state;
 mod worker;
+use footswitch::Footswitch;
 use lv2::prelude::*;
-use std::string::String;
-use time_warp::{Notes, Params, SampleMode, TimeWarp};
+use mono::DmTimeWarpMono;
+use std::{cell::Cell, ops::Range, string::String, sync::Arc};
+use time_warp::{
+  DuckingSettings, EnvelopeDestination, EnvelopeSettings, EqSettings, FileStream, FilterMode,
+  FilterPlacement, FilterSettings, FilterSlope, InputSource, Interpolation, Normalization, Notes,
+  Oversampling, Params, QualitySettings, RecordSettings, ResamplerQuality, ReverbSettings,
+  SampleMode, SampleProcessing, Saturation, SaturationSettings, Settings, ShimmerInterval,
+  ShimmerSettings, TapeSettings, TapeSpeed, TimeWarp, WowFlutterSettings, DEFAULT_MAX_DELAY_TIME,
+};
 use worker::*;
 
+const MAX_BLOCK_SIZE: usize = 64;
+
 #[derive(PortCollection)]
 struct Ports {
   record: InputPort<InPlaceControl>,
@@ -36,12 +48,61 @@ struct Ports {
   voices: InputPort<InPlaceControl>,
   dry: InputPort<InPlaceControl>,
   wet: InputPort<InPlaceControl>,
+  envelope_attack: InputPort<InPlaceControl>,
+  envelope_release: InputPort<InPlaceControl>,
+  envelope_amount: InputPort<InPlaceControl>,
+  envelope_destination: InputPort<InPlaceControl>,
+  gate_threshold: InputPort<InPlaceControl>,
+  record_threshold: InputPort<InPlaceControl>,
+  reverse: InputPort<InPlaceControl>,
+  tape_speed: InputPort<InPlaceControl>,
+  saturation: InputPort<InPlaceControl>,
+  drive: InputPort<InPlaceControl>,
+  wow: InputPort<InPlaceControl>,
+  flutter: InputPort<InPlaceControl>,
+  filter_placement: InputPort<InPlaceControl>,
+  filter_mode: InputPort<InPlaceControl>,
+  filter_slope: InputPort<InPlaceControl>,
+  filter_cutoff: InputPort<InPlaceControl>,
+  filter_resonance: InputPort<InPlaceControl>,
+  eq_low: InputPort<InPlaceControl>,
+  eq_mid: InputPort<InPlaceControl>,
+  eq_mid_freq: InputPort<InPlaceControl>,
+  eq_high: InputPort<InPlaceControl>,
+  reverb_size: InputPort<InPlaceControl>,
+  reverb_decay: InputPort<InPlaceControl>,
+  reverb_mix: InputPort<InPlaceControl>,
+  reverb_to_delay: InputPort<InPlaceControl>,
+  shimmer: InputPort<InPlaceControl>,
+  shimmer_interval: InputPort<InPlaceControl>,
+  duck_threshold: InputPort<InPlaceControl>,
+  duck_amount: InputPort<InPlaceControl>,
+  duck_release: InputPort<InPlaceControl>,
+  record_source: InputPort<InPlaceControl>,
+  envelope_source: InputPort<InPlaceControl>,
+  separate_wet_output: InputPort<InPlaceControl>,
+  interpolation: InputPort<InPlaceControl>,
+  oversampling: InputPort<InPlaceControl>,
+  buffer_length: InputPort<InPlaceControl>,
+  resampler_quality: InputPort<InPlaceControl>,
+  file_sample_rate: InputPort<InPlaceControl>,
+  normalization: InputPort<InPlaceControl>,
+  trim_threshold: InputPort<InPlaceControl>,
+  remove_dc: InputPort<InPlaceControl>,
+  reverse_sample: InputPort<InPlaceControl>,
+  embed_sample: InputPort<InPlaceControl>,
+  latency: OutputPort<InPlaceControl>,
   control: InputPort<AtomPort>,
   notify: OutputPort<AtomPort>,
   input_left: InputPort<InPlaceAudio>,
-  input_right: InputPort<InPlaceAudio>,
   output_left: OutputPort<InPlaceAudio>,
-  output_right: OutputPort<InPlaceAudio>,
+  sidechain_left: Option<InputPort<InPlaceAudio>>,
+  // the right channel ports come last, so the mono plugin can leave them out
+  input_right: Option<InputPort<InPlaceAudio>>,
+  output_right: Option<OutputPort<InPlaceAudio>>,
+  sidechain_right: Option<InputPort<InPlaceAudio>>,
+  wet_left: Option<OutputPort<InPlaceAudio>>,
+  wet_right: Option<OutputPort<InPlaceAudio>>,
 }
 
 #[derive(FeatureCollection)]
@@ -52,11 +113,27 @@ struct InitFeatures<'a> {
 #[derive(FeatureCollection)]
 struct AudioFeatures<'a> {
   schedule: Schedule<'a, DmTimeWarp>,
+  log: Option<Log<'a>>,
 }
 
 #[uri("https://github.com/davemollen/dm-TimeWarp#sample")]
 struct Sample;
 
+#[uri("https://github.com/davemollen/dm-TimeWarp#record")]
+struct Record;
+
+#[uri("https://github.com/davemollen/dm-TimeWarp#play")]
+struct Play;
+
+#[uri("https://github.com/davemollen/dm-TimeWarp#erase")]
+struct Erase;
+
+#[uri("https://github.com/davemollen/dm-TimeWarp#status")]
+struct Status;
+
+#[uri("https://github.com/davemollen/dm-TimeWarp#embedded_sample")]
+struct EmbeddedSample;
+
 #[derive(URIDCollection)]
 struct URIDs {
   atom: AtomURIDCollection,
@@ -65,6 +142,11 @@ struct URIDs {
   patch: PatchURIDCollection,
   log: LogURIDCollection,
   sample: URID<Sample>,
+  record: URID<Record>,
+  play: URID<Play>,
+  erase: URID<Erase>,
+  status: URID<Status>,
+  embedded_sample: URID<EmbeddedSample>,
 }
 
 #[uri("https://github.com/davemollen/dm-TimeWarp")]
@@ -77,8 +159,21 @@ struct DmTimeWarp {
   worker_is_initialized: bool,
   worker_is_finished: bool,
   file_path: String,
+  // the error of the last file that failed to load, which is empty after a successful load
+  file_status: String,
+  // the processed sample encoded as FLAC, which is stored in the state when the sample is embedded
+  embedded_sample: Option<Arc<[u8]>>,
+  embed_sample: bool,
   time_stamp: i64,
   sample_rate: f32,
+  max_delay_time: f32,
+  file_stream: Option<FileStream>,
+  resampler_quality: ResamplerQuality,
+  file_sample_rate: usize,
+  sample_processing: SampleProcessing,
+  record: Footswitch,
+  play: Footswitch,
+  erase: Footswitch,
 }
 
 impl DmTimeWarp {
@@ -89,9 +184,9 @@ impl DmTimeWarp {
     sample_count: u32,
   ) {
     self.params.set(
-      ports.record.get() == 1.,
-      ports.play.get() == 1.,
-      ports.erase.get() == 1.,
+      self.record.process(ports.record.get() == 1.),
+      self.play.process(ports.play.get() == 1.),
+      self.erase.process(ports.erase.get() == 1.),
       ports.scan.get(),
       ports.spray.get(),
       ports.freeze.get() == 1.,
@@ -118,6 +213,107 @@ impl DmTimeWarp {
       ports.sync_position.get() == 1.,
       ports.dry.get(),
       ports.wet.get(),
+      &Settings {
+        record: RecordSettings {
+          threshold: ports.record_threshold.get(),
+          source: Self::map_input_source(ports.record_source.get()),
+        },
+        tape: TapeSettings {
+          speed: match ports.tape_speed.get() {
+            1. => TapeSpeed::Half,
+            3. => TapeSpeed::Double,
+            _ => TapeSpeed::Normal,
+          },
+          reverse: ports.reverse.get() == 1.,
+        },
+        shimmer: ShimmerSettings {
+          amount: ports.shimmer.get(),
+          interval: match ports.shimmer_interval.get() {
+            1. => ShimmerInterval::OctaveDown,
+            2. => ShimmerInterval::Fifth,
+            _ => ShimmerInterval::OctaveUp,
+          },
+        },
+        saturation: SaturationSettings {
+          saturation: match ports.saturation.get() {
+            2. => Saturation::SoftClip,
+            3. => Saturation::Tape,
+            4. => Saturation::Tube,
+            _ => Saturation::Clip,
+          },
+          drive: ports.drive.get(),
+        },
+        wow_flutter: WowFlutterSettings {
+          wow: ports.wow.get(),
+          flutter: ports.flutter.get(),
+        },
+        filter: FilterSettings {
+          placement: match ports.filter_placement.get() {
+            2. => FilterPlacement::Feedback,
+            3. => FilterPlacement::Grains,
+            _ => FilterPlacement::Off,
+          },
+          mode: match ports.filter_mode.get() {
+            2. => FilterMode::Highpass,
+            3. => FilterMode::Bandpass,
+            4. => FilterMode::Notch,
+            _ => FilterMode::Lowpass,
+          },
+          slope: if ports.filter_slope.get() == 2. {
+            FilterSlope::Db24
+          } else {
+            FilterSlope::Db12
+          },
+          cutoff: ports.filter_cutoff.get(),
+          resonance: ports.filter_resonance.get(),
+        },
+        reverb: ReverbSettings {
+          size: ports.reverb_size.get(),
+          decay: ports.reverb_decay.get(),
+          mix: ports.reverb_mix.get(),
+          to_delay: ports.reverb_to_delay.get() == 1.,
+        },
+        eq: EqSettings {
+          low: ports.eq_low.get(),
+          mid: ports.eq_mid.get(),
+          mid_freq: ports.eq_mid_freq.get(),
+          high: ports.eq_high.get(),
+        },
+        envelope: EnvelopeSettings {
+          attack: ports.envelope_attack.get(),
+          release: ports.envelope_release.get(),
+          amount: ports.envelope_amount.get(),
+          destination: match ports.envelope_destination.get() {
+            1. => EnvelopeDestination::Density,
+            2. => EnvelopeDestination::Size,
+            3. => EnvelopeDestination::Wet,
+            4. => EnvelopeDestination::RecordingGain,
+            _ => EnvelopeDestination::FilterCutoff,
+          },
+          gate_threshold: ports.gate_threshold.get(),
+          source: Self::map_input_source(ports.envelope_source.get()),
+        },
+        ducking: DuckingSettings {
+          threshold: ports.duck_threshold.get(),
+          amount: ports.duck_amount.get(),
+          release: ports.duck_release.get(),
+        },
+        quality: QualitySettings {
+          interpolation: match ports.interpolation.get() {
+            1. => Interpolation::Step,
+            3. => Interpolation::Cosine,
+            4. => Interpolation::Cubic,
+            5. => Interpolation::Spline,
+            6. => Interpolation::Sinc,
+            _ => Interpolation::Linear,
+          },
+          oversampling: match ports.oversampling.get() {
+            2. => Oversampling::X2,
+            3. => Oversampling::X4,
+            _ => Oversampling::Off,
+          },
+        },
+      },
       sample_count as usize,
     );
 
@@ -126,13 +322,26 @@ impl DmTimeWarp {
       .get_filter()
       .set_cutoff_frequencies(ports.highpass.get(), ports.lowpass.get());
 
+    if let Some(position) = self.params.get_file_window_request() {
+      if let Some(file_stream) = self.file_stream.take() {
+        features
+          .schedule
+          .schedule_work(WorkRequest::ReadFileWindow(file_stream, position))
+          .ok();
+      }
+    }
+
     if self.params.should_erase_buffer() {
       self.file_path = "".to_string();
+      self.file_status.clear();
+      self.embedded_sample = None;
+      self.file_stream = None;
       self.write_set_file(ports);
       features
         .schedule
         .schedule_work(WorkRequest::FlushBuffer(
-          self.time_warp.get_delay_line_size(),
+          self.max_delay_time,
+          self.sample_rate,
         ))
         .ok();
     }
@@ -142,6 +351,187 @@ impl DmTimeWarp {
     }
 
     self.notes.set_voice_count(ports.voices.get() as usize);
+    ports
+      .latency
+      .set(self.params.oversampling.get_latency() as f32);
+  }
+
+  fn map_resampler_quality(value: f32) -> ResamplerQuality {
+    match value {
+      1. => ResamplerQuality::Fast,
+      2. => ResamplerQuality::Sinc(64),
+      3. => ResamplerQuality::Sinc(256),
+      _ => ResamplerQuality::Fft,
+    }
+  }
+
+  fn map_file_sample_rate(value: f32) -> usize {
+    match value {
+      2. => 48000,
+      3. => 88200,
+      4. => 96000,
+      _ => 44100,
+    }
+  }
+
+  fn get_sample_processing(ports: &mut Ports) -> SampleProcessing {
+    let trim_threshold = ports.trim_threshold.get();
+    SampleProcessing {
+      normalization: match ports.normalization.get() {
+        2. => Normalization::Peak,
+        3. => Normalization::Loudness,
+        _ => Normalization::Off,
+      },
+      trim_threshold: if trim_threshold <= -90. {
+        None
+      } else {
+        Some(trim_threshold)
+      },
+      remove_dc: ports.remove_dc.get() == 1.,
+      reverse: ports.reverse_sample.get() == 1.,
+    }
+  }
+
+  fn map_buffer_length(value: f32) -> f32 {
+    match value {
+      1. => 10000.,
+      2. => 30000.,
+      4. => 120000.,
+      5. => 300000.,
+      _ => 60000.,
+    }
+  }
+
+  /// Reallocates the delay line on the worker thread, which reloads the sample once it's done.
+  fn update_buffer_length(&mut self, ports: &mut Ports, features: &mut AudioFeatures) -> bool {
+    let max_delay_time = Self::map_buffer_length(ports.buffer_length.get());
+    if max_delay_time == self.max_delay_time {
+      return false;
+    }
+    self.max_delay_time = max_delay_time;
+    features
+      .schedule
+      .schedule_work(WorkRequest::ResizeBuffer(max_delay_time, self.sample_rate))
+      .is_ok()
+  }
+
+  /// Reads and encodes the sample on the worker thread when it should be embedded, but isn't yet.
+  fn update_embed_sample(&mut self, ports: &mut Ports, features: &mut AudioFeatures) {
+    let embed_sample = ports.embed_sample.get() == 1.;
+    if embed_sample
+      && !self.embed_sample
+      && self.embedded_sample.is_none()
+      && !self.file_path.is_empty()
+    {
+      features
+        .schedule
+        .schedule_work(self.get_embed_sample_request())
+        .ok();
+    }
+    self.embed_sample = embed_sample;
+  }
+
+  fn map_input_source(value: f32) -> InputSource {
+    if value == 2. {
+      InputSource::Sidechain
+    } else {
+      InputSource::Main
+    }
+  }
+
+  /// Processes the samples from `start` up to `end` with the parameter values at `start`, in blocks
+  /// of at most `MAX_BLOCK_SIZE` samples. The ports are copied, because they may share a buffer.
+  pub fn process_segment(
+    &mut self,
+    ports: &mut Ports,
+    features: &mut AudioFeatures,
+    start: usize,
+    end: usize,
+  ) {
+    self.set_param_values(ports, features, (end - start) as u32);
+
+    let input_count = if ports.input_right.is_some() { 2 } else { 1 };
+    let output_count = if ports.output_right.is_some() { 2 } else { 1 };
+    let sidechain_count = match (&ports.sidechain_left, &ports.sidechain_right) {
+      (Some(_), Some(_)) => 2,
+      (Some(_), None) => 1,
+      _ => 0,
+    };
+    let wet_count = match (&ports.wet_left, &ports.wet_right) {
+      (Some(_), Some(_)) => 2,
+      (Some(_), None) => 1,
+      _ => 0,
+    };
+    let separate_wet_output = ports.separate_wet_output.get() == 1. && wet_count >= output_count;
+
+    for block_start in (start..end).step_by(MAX_BLOCK_SIZE) {
+      let block = block_start..(block_start + MAX_BLOCK_SIZE).min(end);
+      let length = block.len();
+      let mut inputs = [[0.; MAX_BLOCK_SIZE]; 2];
+      let mut sidechain_inputs = [[0.; MAX_BLOCK_SIZE]; 2];
+      let mut outputs = [[0.; MAX_BLOCK_SIZE]; 2];
+      let mut wet_outputs = [[0.; MAX_BLOCK_SIZE]; 2];
+      Self::read_port(&ports.input_left, &block, &mut inputs[0]);
+      if let Some(input_right) = &ports.input_right {
+        Self::read_port(input_right, &block, &mut inputs[1]);
+      }
+      if let Some(sidechain_left) = &ports.sidechain_left {
+        Self::read_port(sidechain_left, &block, &mut sidechain_inputs[0]);
+      }
+      if let Some(sidechain_right) = &ports.sidechain_right {
+        Self::read_port(sidechain_right, &block, &mut sidechain_inputs[1]);
+      }
+
+      let [input_left, input_right] = &inputs;
+      let [sidechain_left, sidechain_right] = &sidechain_inputs;
+      let [output_left, output_right] = &mut outputs;
+      let [wet_left, wet_right] = &mut wet_outputs;
+      let sidechain_slices = [&sidechain_left[..length], &sidechain_right[..length]];
+      let mut output_slices = [&mut output_left[..length], &mut output_right[..length]];
+      let mut wet_slices = [&mut wet_left[..length], &mut wet_right[..length]];
+      self.time_warp.process_block(
+        &[&input_left[..length], &input_right[..length]][..input_count],
+        &mut output_slices[..output_count],
+        if separate_wet_output {
+          Some(&mut wet_slices[..output_count])
+        } else {
+          None
+        },
+        if sidechain_count > 0 {
+          Some(&sidechain_slices[..sidechain_count])
+        } else {
+          None
+        },
+        &mut self.params,
+        self.notes.get_notes(),
+      );
+
+      Self::write_port(&ports.output_left, &block, &outputs[0]);
+      if let Some(output_right) = &ports.output_right {
+        Self::write_port(output_right, &block, &outputs[1]);
+      }
+      // without a separate wet output the wet ports stay silent
+      if let Some(wet_left) = &ports.wet_left {
+        Self::write_port(wet_left, &block, &wet_outputs[0]);
+      }
+      if let Some(wet_right) = &ports.wet_right {
+        Self::write_port(wet_right, &block, &wet_outputs[1]);
+      }
+    }
+  }
+
+  fn read_port(port: &[Cell<f32>], block: &Range<usize>, buffer: &mut [f32]) {
+    buffer
+      .iter_mut()
+      .zip(&port[block.clone()])
+      .for_each(|(sample, cell)| *sample = cell.get());
+  }
+
+  fn write_port(port: &[Cell<f32>], block: &Range<usize>, buffer: &[f32]) {
+    port[block.clone()]
+      .iter()
+      .zip(buffer)
+      .for_each(|(cell, sample)| cell.set(*sample));
   }
 }
 
@@ -154,55 +544,52 @@ impl Plugin for DmTimeWarp {
     let sample_rate = plugin_info.sample_rate() as f32;
 
     Some(Self {
-      time_warp: TimeWarp::new(sample_rate),
-      params: Params::new(sample_rate),
+      time_warp: TimeWarp::new(sample_rate, DEFAULT_MAX_DELAY_TIME),
+      params: Params::new(sample_rate, DEFAULT_MAX_DELAY_TIME),
       urids: features.map.populate_collection()?,
       notes: Notes::new(),
       activated: false,
       worker_is_initialized: false,
       worker_is_finished: false,
       file_path: "".to_string(),
+      file_status: "".to_string(),
+      embedded_sample: None,
+      embed_sample: false,
       time_stamp: 0,
       sample_rate,
+      max_delay_time: DEFAULT_MAX_DELAY_TIME,
+      file_stream: None,
+      resampler_quality: ResamplerQuality::Fft,
+      file_sample_rate: 44100,
+      sample_processing: SampleProcessing::default(),
+      record: Footswitch::new(false),
+      play: Footswitch::new(true),
+      erase: Footswitch::new(false),
     })
   }
 
   fn run(&mut self, ports: &mut Ports, features: &mut Self::AudioFeatures, sample_count: u32) {
+    self.resampler_quality = Self::map_resampler_quality(ports.resampler_quality.get());
+    self.file_sample_rate = Self::map_file_sample_rate(ports.file_sample_rate.get());
+    self.sample_processing = Self::get_sample_processing(ports);
     if self.activated && !self.worker_is_initialized {
-      if !self.file_path.is_empty() {
+      if !self.update_buffer_length(ports, features) && !self.file_path.is_empty() {
         features
           .schedule
-          .schedule_work(WorkRequest::LoadFile(
-            self.file_path.to_string(),
-            self.sample_rate,
-            self.time_warp.get_delay_line_size(),
-          ))
+          .schedule_work(self.get_load_file_request())
           .ok();
       }
       self.worker_is_initialized = true;
       return;
     }
+    self.update_buffer_length(ports, features);
+    self.update_embed_sample(ports, features);
     if self.worker_is_finished {
       self.write_set_file(ports);
       self.worker_is_finished = false;
     }
 
-    self.set_param_values(ports, features, sample_count);
-    self.handle_events(ports, features);
-
-    let input_channels = ports.input_left.iter().zip(ports.input_right.iter());
-    let output_channels = ports.output_left.iter().zip(ports.output_right.iter());
-    for ((input_left, input_right), (output_left, output_right)) in
-      input_channels.zip(output_channels)
-    {
-      let time_warp_output = self.time_warp.process(
-        (input_left.get(), input_right.get()),
-        &mut self.params,
-        &mut self.notes.get_notes(),
-      );
-      output_left.set(time_warp_output.0);
-      output_right.set(time_warp_output.1);
-    }
+    self.handle_events(ports, features, sample_count);
   }
 
   fn extension_data(uri: &Uri) -> Option<&'static dyn std::any::Any> {
@@ -226,4 +613,4 @@ impl Plugin for DmTimeWarp {
 }
 
 // Generate the plugin descriptor function which exports the plugin to the outside world.
-lv2_descriptors!(DmTimeWarp);
+lv2_descriptors!(DmTimeWarp, DmTimeWarpMono);
@@ -0,0 +1,77 @@
+use {
+  crate::{
+    state::StateFeatures,
+    worker::{WorkRequest, WorkResponseData},
+    AudioFeatures, DmTimeWarp, InitFeatures, Ports,
+  },
+  lv2::prelude::*,
+};
+
+/// The mono variant of the plugin, which leaves the right channel ports unconnected.
+#[uri("https://github.com/davemollen/dm-TimeWarp#mono")]
+pub struct DmTimeWarpMono(DmTimeWarp);
+
+impl Plugin for DmTimeWarpMono {
+  type Ports = Ports;
+  type InitFeatures = InitFeatures<'static>;
+  // The work requests are the same as those of the stereo plugin, so its schedule feature is reused.
+  type AudioFeatures = AudioFeatures<'static>;
+
+  fn new(plugin_info: &PluginInfo, features: &mut Self::InitFeatures) -> Option<Self> {
+    <DmTimeWarp as Plugin>::new(plugin_info, features).map(Self)
+  }
+
+  fn run(&mut self, ports: &mut Ports, features: &mut Self::AudioFeatures, sample_count: u32) {
+    self.0.run(ports, features, sample_count);
+  }
+
+  fn extension_data(uri: &Uri) -> Option<&'static dyn std::any::Any> {
+    match_extensions!(uri, StateDescriptor<Self>, WorkerDescriptor<Self>)
+  }
+
+  fn activate(&mut self, features: &mut Self::InitFeatures) {
+    self.0.activate(features);
+  }
+
+  fn deactivate(&mut self, features: &mut Self::InitFeatures) {
+    self.0.deactivate(features);
+  }
+}
+
+impl Worker for DmTimeWarpMono {
+  type WorkData = WorkRequest;
+  type ResponseData = WorkResponseData;
+
+  fn work(
+    response_handler: &ResponseHandler<Self>,
+    data: Self::WorkData,
+  ) -> Result<(), WorkerError> {
+    response_handler
+      .respond(DmTimeWarp::get_work_response(data)?)
+      .or(Err(WorkerError::Unknown))
+  }
+
+  fn work_response(
+    &mut self,
+    data: Self::ResponseData,
+    features: &mut Self::AudioFeatures,
+  ) -> Result<(), WorkerError> {
+    self.0.work_response(data, features)
+  }
+}
+
+impl State for DmTimeWarpMono {
+  type StateFeatures = StateFeatures<'static>;
+
+  fn save(&self, store: StoreHandle, features: Self::StateFeatures) -> Result<(), StateErr> {
+    self.0.save(store, features)
+  }
+
+  fn restore(
+    &mut self,
+    store: RetrieveHandle,
+    features: Self::StateFeatures,
+  ) -> Result<(), StateErr> {
+    self.0.restore(store, features)
+  }
+}
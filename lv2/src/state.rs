@@ -4,7 +4,7 @@ use {
     path::{FreePath, MakePath, MapPath, PathManager},
     *,
   },
-  std::{ffi::CStr, path::Path},
+  std::{ffi::CStr, path::Path, sync::Arc},
 };
 
 #[derive(FeatureCollection)]
@@ -19,6 +19,14 @@ impl State for DmTimeWarp {
   type StateFeatures = StateFeatures<'static>;
 
   fn save(&self, mut store: StoreHandle, features: Self::StateFeatures) -> Result<(), StateErr> {
+    if let (true, Some(embedded_sample)) = (self.embed_sample, &self.embedded_sample) {
+      store
+        .draft(self.urids.embedded_sample)
+        .init(self.urids.atom.chunk, ())?
+        .write_raw(embedded_sample, false)
+        .ok_or(StateErr::Unknown)?;
+    }
+
     match (features.make_path, features.map_path, features.free_path) {
       (Some(make_path), Some(map_path), Some(free_path)) => {
         let mut manager = PathManager::new(make_path, map_path, free_path);
@@ -42,7 +50,7 @@ impl State for DmTimeWarp {
 
         store.commit_all()
       }
-      _ => Ok(()),
+      _ => store.commit_all(),
     }
   }
   fn restore(
@@ -77,6 +85,13 @@ impl State for DmTimeWarp {
           .to_string_lossy()
           .to_string();
 
+        // presets without an embedded sample are read from the file path
+        self.embedded_sample = store
+          .retrieve(self.urids.embedded_sample)
+          .and_then(|property| property.read(self.urids.atom.chunk, ()))
+          .ok()
+          .map(Arc::from);
+
         Ok(())
       }
       _ => Ok(()),
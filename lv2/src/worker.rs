@@ -1,18 +1,256 @@
 use {
-  crate::DmTimeWarp,
+  crate::{AudioFeatures, DmTimeWarp},
   lv2::prelude::*,
-  std::string::String,
-  time_warp::{AudioFileData, AudioFileProcessor},
+  std::{ffi::CString, string::String, sync::Arc},
+  time_warp::{
+    AudioFile, AudioFileProcessingError, AudioFileProcessor, DelayLine, FileStream,
+    ResamplerQuality, SampleProcessing, StreamWindow, TimeWarp,
+  },
 };
 
 pub enum WorkRequest {
-  LoadFile(String, f32, usize),
-  FlushBuffer(usize),
+  LoadFile(
+    String,
+    f32,
+    usize,
+    ResamplerQuality,
+    usize,
+    SampleProcessing,
+    Option<Arc<[u8]>>,
+    bool,
+  ),
+  EmbedSample(
+    String,
+    f32,
+    usize,
+    ResamplerQuality,
+    usize,
+    SampleProcessing,
+  ),
+  FlushBuffer(f32, f32),
+  ResizeBuffer(f32, f32),
+  ReadFileWindow(FileStream, f32),
 }
 
+/// The embedded sample, or the error message for the UI and the message for the log when the
+/// sample can't be embedded.
+type EmbedResult = Result<Option<Arc<[u8]>>, (String, CString)>;
+
+/// The messages are formatted on the worker thread, so the audio thread only has to pass them on.
 pub enum WorkResponseData {
-  LoadFile(AudioFileData),
-  FlushBuffer(Vec<f32>),
+  LoadFile(DelayLine, f32, CString, EmbedResult),
+  StreamFile(FileStream, StreamWindow, CString, EmbedResult),
+  // the error message for the UI and the message for the log
+  LoadFileError(String, CString),
+  EmbedSample(EmbedResult),
+  FlushBuffer(DelayLine, f32),
+  ResizeBuffer(DelayLine, f32),
+  ReadFileWindow(FileStream, StreamWindow),
+  // the stream is handed back, so the next window request can retry
+  ReadFileWindowError(FileStream, String, CString),
+}
+
+impl DmTimeWarp {
+  pub fn get_work_response(data: WorkRequest) -> Result<WorkResponseData, WorkerError> {
+    match data {
+      WorkRequest::LoadFile(
+        file_path,
+        sample_rate,
+        max_size,
+        resampler_quality,
+        file_sample_rate,
+        sample_processing,
+        embedded_sample,
+        embed_sample,
+      ) => {
+        if file_path.is_empty() {
+          return Err(WorkerError::Unknown);
+        }
+        let mut audio_file_processor = AudioFileProcessor::new(
+          sample_rate,
+          max_size,
+          resampler_quality,
+          Some(file_sample_rate),
+          sample_processing,
+        );
+        // a restored preset reads its embedded sample, so it doesn't depend on the file path
+        let result = match &embedded_sample {
+          Some(bytes) => audio_file_processor.open_embedded_sample(bytes.clone()),
+          None => audio_file_processor.open(&file_path),
+        };
+        // a restored sample keeps what's embedded, otherwise the new file is embedded
+        let should_embed = embed_sample && embedded_sample.is_none();
+        let result = result.and_then(|audio_file| match audio_file {
+          AudioFile::Buffered(audio_file_data) => {
+            let message = Self::get_log_message(format!(
+              "Loaded {}: {}",
+              file_path, audio_file_data.sample_rate_conversion
+            ));
+            let embed_result = if should_embed {
+              Self::get_embed_result(&file_path, audio_file_data.encode())
+            } else {
+              Ok(embedded_sample)
+            };
+            // the mip map is built here, so the audio thread only swaps the delay line in
+            let delay_line = TimeWarp::create_delay_line_from_values(
+              audio_file_data.samples,
+              audio_file_data.duration_in_samples,
+              sample_rate,
+            );
+            Ok(WorkResponseData::LoadFile(
+              delay_line,
+              audio_file_data.duration_in_ms,
+              message,
+              embed_result,
+            ))
+          }
+          // files that don't fit in the delay line are streamed, starting with their first part
+          AudioFile::Streamed(mut file_stream) => {
+            let window = file_stream.read_window(0.)?;
+            let message = Self::get_log_message(format!(
+              "Streaming {}: {}",
+              file_path,
+              file_stream.get_sample_rate_conversion()
+            ));
+            // a streamed file is longer than the delay line, which is too large to embed
+            let embed_result = if should_embed {
+              Self::get_embed_result(&file_path, Err(AudioFileProcessingError::EmbedSizeError))
+            } else {
+              Ok(embedded_sample)
+            };
+            Ok(WorkResponseData::StreamFile(
+              file_stream,
+              window,
+              message,
+              embed_result,
+            ))
+          }
+        });
+        Ok(result.unwrap_or_else(|error| {
+          Self::get_load_file_error(format!("Failed to load {}", file_path), error)
+        }))
+      }
+      WorkRequest::EmbedSample(
+        file_path,
+        sample_rate,
+        max_size,
+        resampler_quality,
+        file_sample_rate,
+        sample_processing,
+      ) => {
+        let mut audio_file_processor = AudioFileProcessor::new(
+          sample_rate,
+          max_size,
+          resampler_quality,
+          Some(file_sample_rate),
+          sample_processing,
+        );
+        let result = match audio_file_processor.open(&file_path) {
+          Ok(AudioFile::Buffered(audio_file_data)) => audio_file_data.encode(),
+          Ok(AudioFile::Streamed(_)) => Err(AudioFileProcessingError::EmbedSizeError),
+          Err(error) => Err(error),
+        };
+        Ok(WorkResponseData::EmbedSample(Self::get_embed_result(
+          &file_path, result,
+        )))
+      }
+      // a new delay line is sent, because the current one can be resized before this arrives
+      WorkRequest::FlushBuffer(max_delay_time, sample_rate) => Ok(WorkResponseData::FlushBuffer(
+        TimeWarp::create_delay_line(sample_rate, max_delay_time),
+        max_delay_time,
+      )),
+      WorkRequest::ResizeBuffer(max_delay_time, sample_rate) => Ok(WorkResponseData::ResizeBuffer(
+        TimeWarp::create_delay_line(sample_rate, max_delay_time),
+        max_delay_time,
+      )),
+      WorkRequest::ReadFileWindow(mut file_stream, position) => {
+        match file_stream.read_window(position) {
+          Ok(window) => Ok(WorkResponseData::ReadFileWindow(file_stream, window)),
+          Err(error) => {
+            let message = Self::get_log_message(format!("Failed to stream the file: {}", error));
+            Ok(WorkResponseData::ReadFileWindowError(
+              file_stream,
+              error.to_string(),
+              message,
+            ))
+          }
+        }
+      }
+    }
+  }
+
+  fn get_embed_result(
+    file_path: &str,
+    result: Result<Vec<u8>, AudioFileProcessingError>,
+  ) -> EmbedResult {
+    result.map(|bytes| Some(Arc::from(bytes))).map_err(|error| {
+      let message = Self::get_log_message(format!("Failed to embed {}: {}", file_path, error));
+      (error.to_string(), message)
+    })
+  }
+
+  fn get_load_file_error(description: String, error: AudioFileProcessingError) -> WorkResponseData {
+    let message = Self::get_log_message(format!("{}: {}", description, error));
+    WorkResponseData::LoadFileError(error.to_string(), message)
+  }
+
+  fn get_log_message(message: String) -> CString {
+    CString::new(message + "\n").unwrap_or_default()
+  }
+
+  pub fn get_load_file_request(&self) -> WorkRequest {
+    WorkRequest::LoadFile(
+      self.file_path.to_string(),
+      self.sample_rate,
+      self.time_warp.get_delay_line_size(),
+      self.resampler_quality,
+      self.file_sample_rate,
+      self.sample_processing,
+      self.embedded_sample.clone(),
+      self.embed_sample,
+    )
+  }
+
+  pub fn get_embed_sample_request(&self) -> WorkRequest {
+    WorkRequest::EmbedSample(
+      self.file_path.to_string(),
+      self.sample_rate,
+      self.time_warp.get_delay_line_size(),
+      self.resampler_quality,
+      self.file_sample_rate,
+      self.sample_processing,
+    )
+  }
+
+  /// Stores the embedded sample, or clears it and reports why the sample isn't embedded.
+  fn set_embedded_sample(&mut self, embed_result: EmbedResult, features: &mut AudioFeatures) {
+    match embed_result {
+      Ok(embedded_sample) => self.embedded_sample = embedded_sample,
+      Err((status, message)) => {
+        self.embedded_sample = None;
+        self.file_status = status;
+        if let Some(log) = &features.log {
+          log.print_cstr(self.urids.log.error, &message).ok();
+        }
+      }
+    }
+  }
+
+  /// Sets the first window of a streamed file, or crossfades to a later one.
+  fn set_file_window(&mut self, file_stream: FileStream, window: StreamWindow, is_first: bool) {
+    if is_first {
+      self
+        .time_warp
+        .set_delay_line(window.delay_line, self.max_delay_time);
+    } else {
+      self.time_warp.set_stream_window(window.delay_line);
+    }
+    self.params.set_file_duration(window.duration_in_ms);
+    self
+      .params
+      .set_file_window(window.start_in_ms, window.file_duration_in_ms);
+    self.file_stream = Some(file_stream);
+  }
 }
 
 impl Worker for DmTimeWarp {
@@ -23,45 +261,79 @@ impl Worker for DmTimeWarp {
     response_handler: &ResponseHandler<Self>,
     data: Self::WorkData,
   ) -> Result<(), WorkerError> {
-    match data {
-      WorkRequest::LoadFile(file_path, sample_rate, max_size) => {
-        if file_path.is_empty() {
-          return Err(WorkerError::Unknown);
-        }
-        let audio_file_data = AudioFileProcessor::new(sample_rate, max_size)
-          .read(&file_path)
-          .or(Err(WorkerError::Unknown))?;
-
-        response_handler
-          .respond(WorkResponseData::LoadFile(audio_file_data))
-          .or(Err(WorkerError::Unknown))
-      }
-      WorkRequest::FlushBuffer(size) => response_handler
-        .respond(WorkResponseData::FlushBuffer(vec![0.; size]))
-        .or(Err(WorkerError::Unknown)),
-    }
+    response_handler
+      .respond(Self::get_work_response(data)?)
+      .or(Err(WorkerError::Unknown))
   }
 
   fn work_response(
     &mut self,
     data: Self::ResponseData,
-    _features: &mut Self::AudioFeatures,
+    features: &mut Self::AudioFeatures,
   ) -> Result<(), WorkerError> {
     match data {
-      WorkResponseData::LoadFile(AudioFileData {
-        samples,
-        duration_in_samples,
-        duration_in_ms,
-      }) => {
+      WorkResponseData::LoadFile(delay_line, duration_in_ms, message, embed_result) => {
         self
           .time_warp
-          .set_delay_line_values(samples, duration_in_samples);
+          .set_delay_line(delay_line, self.max_delay_time);
         self.params.set_file_duration(duration_in_ms);
         self.params.reset_playback();
+        self.file_stream = None;
+        self.file_status.clear();
+        self.worker_is_finished = true;
+        if let Some(log) = &features.log {
+          log.print_cstr(self.urids.log.note, &message).ok();
+        }
+        self.set_embedded_sample(embed_result, features);
+      }
+      WorkResponseData::StreamFile(file_stream, window, message, embed_result) => {
+        self.set_file_window(file_stream, window, true);
+        self.params.reset_playback();
+        self.file_status.clear();
         self.worker_is_finished = true;
+        if let Some(log) = &features.log {
+          log.print_cstr(self.urids.log.note, &message).ok();
+        }
+        self.set_embedded_sample(embed_result, features);
+      }
+      WorkResponseData::LoadFileError(status, message) => {
+        self.file_status = status;
+        self.worker_is_finished = true;
+        if let Some(log) = &features.log {
+          log.print_cstr(self.urids.log.error, &message).ok();
+        }
+      }
+      WorkResponseData::ReadFileWindow(file_stream, window) => {
+        self.set_file_window(file_stream, window, false);
       }
-      WorkResponseData::FlushBuffer(samples) => {
-        self.time_warp.set_delay_line_values(samples, 0);
+      WorkResponseData::ReadFileWindowError(file_stream, status, message) => {
+        self.file_stream = Some(file_stream);
+        self.params.cancel_file_window_request();
+        self.file_status = status;
+        self.worker_is_finished = true;
+        if let Some(log) = &features.log {
+          log.print_cstr(self.urids.log.error, &message).ok();
+        }
+      }
+      WorkResponseData::EmbedSample(embed_result) => {
+        self.set_embedded_sample(embed_result, features);
+      }
+      WorkResponseData::FlushBuffer(delay_line, max_delay_time) => {
+        // this replaces a stream window as well, which a window response can have swapped in
+        // after the stream was dropped
+        self.time_warp.set_delay_line(delay_line, max_delay_time);
+        self.file_stream = None;
+      }
+      WorkResponseData::ResizeBuffer(delay_line, max_delay_time) => {
+        self.time_warp.set_delay_line(delay_line, max_delay_time);
+        self.params.set_max_delay_time(max_delay_time);
+        if !self.file_path.is_empty() {
+          // read the sample again, now that it can be cut off at a different length
+          features
+            .schedule
+            .schedule_work(self.get_load_file_request())
+            .ok();
+        }
       }
     }
 
@@ -133,6 +133,7 @@ pub(crate) fn create(
               cx,
               async_executor.clone(),
               Data::params.map(|p| p.file_path.lock().unwrap().clone()),
+              Data::params.map(|p| p.file_status.lock().unwrap().clone()),
               "Sample".to_string(),
             )
             .size(Auto)
@@ -14,14 +14,16 @@ pub struct ParamFileDrop {
 }
 
 impl ParamFileDrop {
-  pub fn new<L>(
+  pub fn new<L, S>(
     cx: &mut Context,
     async_executor: AsyncExecutor<DmTimeWarp>,
     lens: L,
+    status_lens: S,
     label_text: String,
   ) -> Handle<'_, Self>
   where
     L: Lens<Target = String>,
+    S: Lens<Target = String>,
   {
     Self { async_executor }.build(cx, |cx| {
       VStack::new(cx, |cx| {
@@ -54,6 +56,13 @@ impl ParamFileDrop {
             cx.emit(ParamFileDropEvent::SetFilePath(path_buf));
           }
         });
+
+        Label::new(cx, status_lens)
+          .font_size(8.0)
+          .text_wrap(false)
+          .width(Pixels(104.0))
+          .child_space(Stretch(1.0))
+          .class("file-status");
       })
       .size(Auto)
       .child_space(Stretch(1.0))
@@ -3,36 +3,60 @@ mod time_warp_parameters;
 mod worker;
 use {
   nih_plug::prelude::*,
-  std::sync::{atomic::Ordering, Arc},
-  time_warp::{AudioFileData, Notes, Params as ProcessParams, SampleMode, TimeWarp},
-  time_warp_parameters::{SampleMode as ParamSampleMode, TimeWarpParameters},
+  std::{
+    ops::Range,
+    sync::{atomic::Ordering, Arc},
+  },
+  time_warp::{
+    DuckingSettings, EnvelopeDestination, EnvelopeSettings, EqSettings, FileStream, FilterMode,
+    FilterPlacement, FilterSettings, FilterSlope, InputSource, Interpolation, Notes, Oversampling,
+    Params as ProcessParams, QualitySettings, RecordSettings, ReverbSettings, SampleMode,
+    Saturation, SaturationSettings, Settings, ShimmerInterval, ShimmerSettings, StreamWindow,
+    TapeSettings, TapeSpeed, TimeWarp, WowFlutterSettings, DEFAULT_MAX_DELAY_TIME,
+  },
+  time_warp_parameters::{
+    BufferLength as ParamBufferLength, EnvelopeDestination as ParamEnvelopeDestination,
+    FilterMode as ParamFilterMode, FilterPlacement as ParamFilterPlacement,
+    FilterSlope as ParamFilterSlope, InputSource as ParamInputSource,
+    Interpolation as ParamInterpolation, Oversampling as ParamOversampling,
+    SampleMode as ParamSampleMode, Saturation as ParamSaturation,
+    ShimmerInterval as ParamShimmerInterval, TapeSpeed as ParamTapeSpeed, TimeWarpParameters,
+  },
   worker::{Worker, WorkerRequest, WorkerResponseData},
 };
 
+const MAX_BLOCK_SIZE: usize = 64;
+
 pub struct DmTimeWarp {
   params: Arc<TimeWarpParameters>,
   time_warp: TimeWarp,
   process_params: ProcessParams,
   notes: Notes,
   worker: Worker,
+  is_mono_input: bool,
+  latency: u32,
+  max_delay_time: f32,
+  file_stream: Option<FileStream>,
+  embed_sample: bool,
 }
 
 impl Default for DmTimeWarp {
   fn default() -> Self {
     let sample_rate = 44100_f32;
     let params = Arc::new(TimeWarpParameters::default());
-    let time_warp = TimeWarp::new(sample_rate);
+    let time_warp = TimeWarp::new(sample_rate, DEFAULT_MAX_DELAY_TIME);
 
     Self {
       params: params.clone(),
-      time_warp: TimeWarp::new(sample_rate),
-      process_params: ProcessParams::new(sample_rate),
+      time_warp: TimeWarp::new(sample_rate, DEFAULT_MAX_DELAY_TIME),
+      process_params: ProcessParams::new(sample_rate, DEFAULT_MAX_DELAY_TIME),
       notes: Notes::new(),
-      worker: Worker::new(
-        sample_rate,
-        params.file_path.clone(),
-        time_warp.get_delay_line_size(),
-      ),
+      worker: Worker::new(sample_rate, params.clone(), time_warp.get_delay_line_size()),
+      is_mono_input: false,
+      latency: 0,
+      max_delay_time: DEFAULT_MAX_DELAY_TIME,
+      file_stream: None,
+      embed_sample: false,
     }
   }
 }
@@ -69,17 +93,123 @@ impl DmTimeWarp {
       self.params.sync_position.value(),
       self.params.dry.value(),
       self.params.wet.value(),
+      &Settings {
+        record: RecordSettings {
+          threshold: self.params.record_threshold.value(),
+          source: Self::map_input_source(self.params.record_source.value()),
+        },
+        tape: TapeSettings {
+          speed: match self.params.tape_speed.value() {
+            ParamTapeSpeed::Half => TapeSpeed::Half,
+            ParamTapeSpeed::Normal => TapeSpeed::Normal,
+            ParamTapeSpeed::Double => TapeSpeed::Double,
+          },
+          reverse: self.params.reverse.value(),
+        },
+        shimmer: ShimmerSettings {
+          amount: self.params.shimmer.value(),
+          interval: match self.params.shimmer_interval.value() {
+            ParamShimmerInterval::OctaveDown => ShimmerInterval::OctaveDown,
+            ParamShimmerInterval::Fifth => ShimmerInterval::Fifth,
+            ParamShimmerInterval::OctaveUp => ShimmerInterval::OctaveUp,
+          },
+        },
+        saturation: SaturationSettings {
+          saturation: match self.params.saturation.value() {
+            ParamSaturation::Clip => Saturation::Clip,
+            ParamSaturation::SoftClip => Saturation::SoftClip,
+            ParamSaturation::Tape => Saturation::Tape,
+            ParamSaturation::Tube => Saturation::Tube,
+          },
+          drive: self.params.drive.value(),
+        },
+        wow_flutter: WowFlutterSettings {
+          wow: self.params.wow.value(),
+          flutter: self.params.flutter.value(),
+        },
+        filter: FilterSettings {
+          placement: match self.params.filter_placement.value() {
+            ParamFilterPlacement::Off => FilterPlacement::Off,
+            ParamFilterPlacement::Feedback => FilterPlacement::Feedback,
+            ParamFilterPlacement::Grains => FilterPlacement::Grains,
+          },
+          mode: match self.params.filter_mode.value() {
+            ParamFilterMode::Lowpass => FilterMode::Lowpass,
+            ParamFilterMode::Highpass => FilterMode::Highpass,
+            ParamFilterMode::Bandpass => FilterMode::Bandpass,
+            ParamFilterMode::Notch => FilterMode::Notch,
+          },
+          slope: match self.params.filter_slope.value() {
+            ParamFilterSlope::Db12 => FilterSlope::Db12,
+            ParamFilterSlope::Db24 => FilterSlope::Db24,
+          },
+          cutoff: self.params.filter_cutoff.value(),
+          resonance: self.params.filter_resonance.value(),
+        },
+        reverb: ReverbSettings {
+          size: self.params.reverb_size.value(),
+          decay: self.params.reverb_decay.value(),
+          mix: self.params.reverb_mix.value(),
+          to_delay: self.params.reverb_to_delay.value(),
+        },
+        eq: EqSettings {
+          low: self.params.eq_low.value(),
+          mid: self.params.eq_mid.value(),
+          mid_freq: self.params.eq_mid_freq.value(),
+          high: self.params.eq_high.value(),
+        },
+        envelope: EnvelopeSettings {
+          attack: self.params.envelope_attack.value(),
+          release: self.params.envelope_release.value(),
+          amount: self.params.envelope_amount.value(),
+          destination: match self.params.envelope_destination.value() {
+            ParamEnvelopeDestination::Density => EnvelopeDestination::Density,
+            ParamEnvelopeDestination::Size => EnvelopeDestination::Size,
+            ParamEnvelopeDestination::Wet => EnvelopeDestination::Wet,
+            ParamEnvelopeDestination::RecordingGain => EnvelopeDestination::RecordingGain,
+            ParamEnvelopeDestination::FilterCutoff => EnvelopeDestination::FilterCutoff,
+          },
+          gate_threshold: self.params.gate_threshold.value(),
+          source: Self::map_input_source(self.params.envelope_source.value()),
+        },
+        ducking: DuckingSettings {
+          threshold: self.params.duck_threshold.value(),
+          amount: self.params.duck_amount.value(),
+          release: self.params.duck_release.value(),
+        },
+        quality: QualitySettings {
+          interpolation: match self.params.interpolation.value() {
+            ParamInterpolation::Step => Interpolation::Step,
+            ParamInterpolation::Linear => Interpolation::Linear,
+            ParamInterpolation::Cosine => Interpolation::Cosine,
+            ParamInterpolation::Cubic => Interpolation::Cubic,
+            ParamInterpolation::Spline => Interpolation::Spline,
+            ParamInterpolation::Sinc => Interpolation::Sinc,
+          },
+          oversampling: Self::map_oversampling(self.params.oversampling.value()),
+        },
+      },
       buffer_size,
     );
+    self.update_latency(context);
+    self.update_buffer_length(context);
+    self.update_embed_sample(context);
 
     self
       .time_warp
       .get_filter()
       .set_cutoff_frequencies(self.params.highpass.value(), self.params.lowpass.value());
 
+    if let Some(position) = self.process_params.get_file_window_request() {
+      if let Some(file_stream) = self.file_stream.take() {
+        context.execute_background(WorkerRequest::ReadFileWindow(file_stream, position));
+      }
+    }
+
     if self.process_params.should_erase_buffer() {
       *self.params.file_path.lock().unwrap() = "".to_string();
-      context.execute_background(WorkerRequest::FlushBuffer);
+      self.file_stream = None;
+      context.execute_background(WorkerRequest::FlushBuffer(self.max_delay_time));
     }
 
     if self.process_params.should_remove_notes() {
@@ -91,30 +221,25 @@ impl DmTimeWarp {
       .set_voice_count(self.params.voices.value() as usize);
   }
 
-  fn process_midi_events(&mut self, context: &mut impl ProcessContext<Self>) {
-    if self.process_params.midi_enabled {
-      // while is needed because events come in batches
-      while let Some(event) = context.next_event() {
-        match event {
-          NoteEvent::NoteOn { note, velocity, .. } => {
-            self.notes.note_on(note, velocity);
-          }
-          NoteEvent::NoteOff { note, .. } => {
-            self.notes.note_off(note);
-          }
-          NoteEvent::MidiCC { cc, value, .. } => match cc {
-            64 => self.notes.sustain(value > 0.),
-            120 => self.notes.remove_notes(),
-            123 => self.notes.release_notes(),
-            _ => (),
-          },
-          NoteEvent::MidiPitchBend { value, .. } => {
-            let pitchbend_factor = 2f32.powf(value * 2. - 1.);
-            self.process_params.set_pitch_bend_factor(pitchbend_factor);
-          }
-          _ => (),
-        }
+  fn process_midi_event(&mut self, event: NoteEvent<()>) {
+    match event {
+      NoteEvent::NoteOn { note, velocity, .. } => {
+        self.notes.note_on(note, velocity);
+      }
+      NoteEvent::NoteOff { note, .. } => {
+        self.notes.note_off(note);
+      }
+      NoteEvent::MidiCC { cc, value, .. } => match cc {
+        64 => self.notes.sustain(value > 0.),
+        120 => self.notes.remove_notes(),
+        123 => self.notes.release_notes(),
+        _ => (),
+      },
+      NoteEvent::MidiPitchBend { value, .. } => {
+        let pitchbend_factor = 2f32.powf(value * 2. - 1.);
+        self.process_params.set_pitch_bend_factor(pitchbend_factor);
       }
+      _ => (),
     }
   }
 
@@ -153,6 +278,142 @@ impl DmTimeWarp {
     }
   }
 
+  fn map_oversampling(oversampling: ParamOversampling) -> Oversampling {
+    match oversampling {
+      ParamOversampling::Off => Oversampling::Off,
+      ParamOversampling::X2 => Oversampling::X2,
+      ParamOversampling::X4 => Oversampling::X4,
+    }
+  }
+
+  fn update_latency(&mut self, context: &mut impl ProcessContext<Self>) {
+    let latency = self.process_params.oversampling.get_latency() as u32;
+    if latency != self.latency {
+      self.latency = latency;
+      context.set_latency_samples(latency);
+    }
+  }
+
+  fn map_buffer_length(buffer_length: ParamBufferLength) -> f32 {
+    match buffer_length {
+      ParamBufferLength::S10 => 10000.,
+      ParamBufferLength::S30 => 30000.,
+      ParamBufferLength::M1 => 60000.,
+      ParamBufferLength::M2 => 120000.,
+      ParamBufferLength::M5 => 300000.,
+    }
+  }
+
+  /// The delay line is reallocated on the worker thread, which also reloads the sample into it.
+  fn update_buffer_length(&mut self, context: &mut impl ProcessContext<Self>) {
+    let max_delay_time = Self::map_buffer_length(self.params.buffer_length.value());
+    if max_delay_time != self.max_delay_time {
+      self.max_delay_time = max_delay_time;
+      self
+        .params
+        .max_delay_time
+        .store(max_delay_time, Ordering::Relaxed);
+      context.execute_background(WorkerRequest::ResizeBuffer(max_delay_time));
+    }
+  }
+
+  /// The sample is read into the plugin state, or removed from it, on the worker thread.
+  fn update_embed_sample(&mut self, context: &mut impl ProcessContext<Self>) {
+    let embed_sample = self.params.embed_sample.value();
+    if embed_sample != self.embed_sample {
+      self.embed_sample = embed_sample;
+      context.execute_background(WorkerRequest::EmbedSample);
+    }
+  }
+
+  /// Sets the first window of a streamed file, or crossfades to a later one.
+  fn set_file_window(&mut self, file_stream: FileStream, window: StreamWindow, is_first: bool) {
+    if is_first {
+      self
+        .time_warp
+        .set_delay_line(window.delay_line, self.max_delay_time);
+    } else {
+      self.time_warp.set_stream_window(window.delay_line);
+    }
+    self.process_params.set_file_duration(window.duration_in_ms);
+    self
+      .process_params
+      .set_file_window(window.start_in_ms, window.file_duration_in_ms);
+    self.file_stream = Some(file_stream);
+  }
+
+  fn map_input_source(input_source: ParamInputSource) -> InputSource {
+    match input_source {
+      ParamInputSource::Main => InputSource::Main,
+      ParamInputSource::Sidechain => InputSource::Sidechain,
+    }
+  }
+
+  /// Processes the samples in `range` in blocks of at most `MAX_BLOCK_SIZE` samples. The inputs
+  /// are copied first, because the buffer is processed in place.
+  fn process_range(
+    &mut self,
+    channels: &mut [&mut [f32]],
+    mut wet_channels: Option<&mut [&mut [f32]]>,
+    sidechain: Option<&[&[f32]]>,
+    range: Range<usize>,
+    separate_wet_output: bool,
+  ) {
+    // the buffer has as many channels as the output, where a mono input is in the first channel
+    let output_count = channels.len().min(2);
+    let input_count = if self.is_mono_input { 1 } else { output_count };
+    let sidechain_count = sidechain.map_or(0, |sidechain| sidechain.len().min(2));
+    let has_wet_output = separate_wet_output
+      && wet_channels
+        .as_ref()
+        .is_some_and(|wet_channels| wet_channels.len() >= output_count);
+
+    for block_start in range.clone().step_by(MAX_BLOCK_SIZE) {
+      let block = block_start..(block_start + MAX_BLOCK_SIZE).min(range.end);
+      let length = block.len();
+      let mut inputs = [[0.; MAX_BLOCK_SIZE]; 2];
+      let mut sidechain_inputs = [[0.; MAX_BLOCK_SIZE]; 2];
+      let mut wet_outputs = [[0.; MAX_BLOCK_SIZE]; 2];
+      for (input, channel) in inputs.iter_mut().zip(channels.iter()).take(input_count) {
+        input[..length].copy_from_slice(&channel[block.clone()]);
+      }
+      if let Some(sidechain) = sidechain {
+        for (input, channel) in sidechain_inputs.iter_mut().zip(sidechain.iter()) {
+          input[..length].copy_from_slice(&channel[block.clone()]);
+        }
+      }
+
+      let [input_left, input_right] = &inputs;
+      let [sidechain_left, sidechain_right] = &sidechain_inputs;
+      let [wet_left, wet_right] = &mut wet_outputs;
+      let mut outputs: [&mut [f32]; 2] = match channels {
+        [left, right, ..] => [&mut left[block.clone()], &mut right[block.clone()]],
+        [output] => [&mut output[block.clone()], &mut []],
+        [] => return,
+      };
+      let sidechain_slices = [&sidechain_left[..length], &sidechain_right[..length]];
+      let mut wet_slices: [&mut [f32]; 2] = [&mut wet_left[..length], &mut wet_right[..length]];
+      self.time_warp.process_block(
+        &[&input_left[..length], &input_right[..length]][..input_count],
+        &mut outputs[..output_count],
+        if has_wet_output {
+          Some(&mut wet_slices[..output_count])
+        } else {
+          None
+        },
+        sidechain.map(|_| &sidechain_slices[..sidechain_count]),
+        &mut self.process_params,
+        self.notes.get_notes(),
+      );
+      // without a separate wet output the aux output stays silent
+      if let Some(wet_channels) = wet_channels.as_mut() {
+        for (channel, wet_output) in wet_channels.iter_mut().zip(wet_outputs.iter()) {
+          channel[block.clone()].copy_from_slice(&wet_output[..length]);
+        }
+      }
+    }
+  }
+
   fn update_max_size_param(&mut self) {
     let target_time = self.process_params.get_target_time();
     if target_time != self.params.max_size.load(Ordering::Relaxed) {
@@ -168,11 +429,44 @@ impl Plugin for DmTimeWarp {
   const EMAIL: &'static str = "davemollen@gmail.com";
   const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-  const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-    main_input_channels: NonZeroU32::new(2),
-    main_output_channels: NonZeroU32::new(2),
-    ..AudioIOLayout::const_default()
-  }];
+  const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
+    AudioIOLayout {
+      main_input_channels: NonZeroU32::new(2),
+      main_output_channels: NonZeroU32::new(2),
+      aux_input_ports: &[new_nonzero_u32(2)],
+      aux_output_ports: &[new_nonzero_u32(2)],
+      names: PortNames {
+        aux_inputs: &["Sidechain"],
+        aux_outputs: &["Wet"],
+        ..PortNames::const_default()
+      },
+      ..AudioIOLayout::const_default()
+    },
+    AudioIOLayout {
+      main_input_channels: NonZeroU32::new(1),
+      main_output_channels: NonZeroU32::new(2),
+      aux_input_ports: &[new_nonzero_u32(1)],
+      aux_output_ports: &[new_nonzero_u32(2)],
+      names: PortNames {
+        aux_inputs: &["Sidechain"],
+        aux_outputs: &["Wet"],
+        ..PortNames::const_default()
+      },
+      ..AudioIOLayout::const_default()
+    },
+    AudioIOLayout {
+      main_input_channels: NonZeroU32::new(1),
+      main_output_channels: NonZeroU32::new(1),
+      aux_input_ports: &[new_nonzero_u32(1)],
+      aux_output_ports: &[new_nonzero_u32(1)],
+      names: PortNames {
+        aux_inputs: &["Sidechain"],
+        aux_outputs: &["Wet"],
+        ..PortNames::const_default()
+      },
+      ..AudioIOLayout::const_default()
+    },
+  ];
   const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
   const SAMPLE_ACCURATE_AUTOMATION: bool = true;
 
@@ -193,12 +487,21 @@ impl Plugin for DmTimeWarp {
 
   fn initialize(
     &mut self,
-    _audio_io_layout: &AudioIOLayout,
+    audio_io_layout: &AudioIOLayout,
     buffer_config: &BufferConfig,
     context: &mut impl InitContext<Self>,
   ) -> bool {
-    self.time_warp = TimeWarp::new(buffer_config.sample_rate);
-    self.process_params = ProcessParams::new(buffer_config.sample_rate);
+    self.is_mono_input = audio_io_layout.main_input_channels == NonZeroU32::new(1);
+    self.latency = Self::map_oversampling(self.params.oversampling.value()).get_latency() as u32;
+    context.set_latency_samples(self.latency);
+    self.max_delay_time = Self::map_buffer_length(self.params.buffer_length.value());
+    self
+      .params
+      .max_delay_time
+      .store(self.max_delay_time, Ordering::Relaxed);
+    self.embed_sample = self.params.embed_sample.value();
+    self.time_warp = TimeWarp::new(buffer_config.sample_rate, self.max_delay_time);
+    self.process_params = ProcessParams::new(buffer_config.sample_rate, self.max_delay_time);
     self.worker.initialize(
       buffer_config.sample_rate,
       self.time_warp.get_delay_line_size(),
@@ -232,43 +535,78 @@ impl Plugin for DmTimeWarp {
   fn process(
     &mut self,
     buffer: &mut Buffer,
-    _aux: &mut AuxiliaryBuffers,
+    aux: &mut AuxiliaryBuffers,
     context: &mut impl ProcessContext<Self>,
   ) -> ProcessStatus {
+    // Parameter changes are applied at their sample offset, because SAMPLE_ACCURATE_AUTOMATION
+    // splits the buffer at every automation event.
     self.set_param_values(buffer.samples(), context);
-    self.process_midi_events(context);
     self.update_max_size_param();
 
     if let Some(worker_response_data) = self.worker.try_receive_data() {
       match worker_response_data {
-        WorkerResponseData::LoadFile(AudioFileData {
-          samples,
-          duration_in_samples,
-          duration_in_ms,
-        }) => {
+        WorkerResponseData::LoadFile(delay_line, duration_in_ms) => {
           self
             .time_warp
-            .set_delay_line_values(samples, duration_in_samples);
+            .set_delay_line(delay_line, self.max_delay_time);
           self.process_params.set_file_duration(duration_in_ms);
           self.process_params.reset_playback();
+          self.file_stream = None;
+        }
+        WorkerResponseData::StreamFile(file_stream, window) => {
+          self.set_file_window(file_stream, window, true);
+          self.process_params.reset_playback();
+        }
+        WorkerResponseData::ReadFileWindow(file_stream, window) => {
+          self.set_file_window(file_stream, window, false);
         }
-        WorkerResponseData::FlushBuffer(samples) => {
-          self.time_warp.set_delay_line_values(samples, 0);
+        WorkerResponseData::ReadFileWindowError(file_stream) => {
+          self.file_stream = Some(file_stream);
+          self.process_params.cancel_file_window_request();
+        }
+        WorkerResponseData::FlushBuffer(delay_line, max_delay_time) => {
+          // this replaces a stream window as well, which a window response can have swapped in
+          // after the stream was dropped
+          self.time_warp.set_delay_line(delay_line, max_delay_time);
+          self.file_stream = None;
+        }
+        WorkerResponseData::ResizeBuffer(delay_line, max_delay_time) => {
+          self.time_warp.set_delay_line(delay_line, max_delay_time);
+          self.process_params.set_max_delay_time(max_delay_time);
         }
       }
     }
 
-    buffer.iter_samples().for_each(|mut channel_samples| {
-      let channel_iterator = &mut channel_samples.iter_mut();
-      let left_channel = channel_iterator.next().unwrap();
-      let right_channel = channel_iterator.next().unwrap();
-
-      (*left_channel, *right_channel) = self.time_warp.process(
-        (*left_channel, *right_channel),
-        &mut self.process_params,
-        &mut self.notes.get_notes(),
+    let sidechain = aux.inputs.first().map(|buffer| buffer.as_slice_immutable());
+    let separate_wet_output = self.params.separate_wet_output.value();
+    let mut wet_channels = aux.outputs.first_mut().map(|buffer| buffer.as_slice());
+    let sample_count = buffer.samples();
+    let channels = buffer.as_slice();
+    let mut next_event = context.next_event();
+    let mut block_start = 0;
+    while block_start < sample_count {
+      while let Some(event) = next_event {
+        if event.timing() > block_start as u32 {
+          break;
+        }
+        if self.process_params.midi_enabled {
+          self.process_midi_event(event);
+        }
+        next_event = context.next_event();
+      }
+      // a block ends at the next event, so notes still start at their sample offset
+      let block_end = next_event.map_or(sample_count, |event| {
+        (event.timing() as usize).min(sample_count)
+      });
+      self.process_range(
+        channels,
+        wet_channels.as_deref_mut(),
+        sidechain,
+        block_start..block_end,
+        separate_wet_output,
       );
-    });
+      block_start = block_end;
+    }
     ProcessStatus::Normal
   }
 
@@ -284,6 +622,7 @@ impl ClapPlugin for DmTimeWarp {
   const CLAP_SUPPORT_URL: Option<&'static str> = None;
   const CLAP_FEATURES: &'static [ClapFeature] = &[
     ClapFeature::AudioEffect,
+    ClapFeature::Mono,
     ClapFeature::Stereo,
     ClapFeature::Instrument,
     ClapFeature::Granular,
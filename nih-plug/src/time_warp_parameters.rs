@@ -1,10 +1,14 @@
 mod custom_formatters;
+pub mod embedded_sample;
 use {
   crate::{
     editor,
-    time_warp_parameters::custom_formatters::{
-      s2v_f32_ms_then_s, s2v_f32_synced_time, s2v_size, v2s_f32_ms_then_s, v2s_f32_synced_time,
-      v2s_size,
+    time_warp_parameters::{
+      custom_formatters::{
+        s2v_f32_ms_then_s, s2v_f32_synced_time, s2v_size, v2s_f32_ms_then_s, v2s_f32_synced_time,
+        v2s_size, v2s_time,
+      },
+      embedded_sample::EmbeddedSample,
     },
   },
   nih_plug::{
@@ -17,10 +21,11 @@ use {
   },
   nih_plug_vizia::ViziaState,
   std::sync::{Arc, Mutex},
-  time_warp::{MAX_DENSITY, MAX_VOICE_COUNT, MIN_DELAY_TIME, MIN_DENSITY},
+  time_warp::{DEFAULT_MAX_DELAY_TIME, MAX_DENSITY, MAX_VOICE_COUNT, MIN_DELAY_TIME, MIN_DENSITY},
 };
 
-const MAX_PARAM_DELAY_TIME: f32 = 10000.;
+// the longest buffer length, times beyond the current buffer length are clamped
+const MAX_PARAM_DELAY_TIME: f32 = 300000.;
 
 #[derive(Enum, PartialEq)]
 pub enum SampleMode {
@@ -29,6 +34,137 @@ pub enum SampleMode {
   Sampler,
 }
 
+#[derive(Enum, PartialEq)]
+pub enum InputSource {
+  Main,
+  Sidechain,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum TapeSpeed {
+  #[name = "1/2x"]
+  Half,
+  #[name = "1x"]
+  Normal,
+  #[name = "2x"]
+  Double,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum ShimmerInterval {
+  #[name = "-12"]
+  OctaveDown,
+  #[name = "+7"]
+  Fifth,
+  #[name = "+12"]
+  OctaveUp,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum Saturation {
+  Clip,
+  #[name = "Soft Clip"]
+  SoftClip,
+  Tape,
+  Tube,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum FilterPlacement {
+  Off,
+  Feedback,
+  Grains,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum FilterMode {
+  Lowpass,
+  Highpass,
+  Bandpass,
+  Notch,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum FilterSlope {
+  #[name = "12 dB"]
+  Db12,
+  #[name = "24 dB"]
+  Db24,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum EnvelopeDestination {
+  Density,
+  Size,
+  Wet,
+  #[name = "Record"]
+  RecordingGain,
+  #[name = "Cutoff"]
+  FilterCutoff,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum Interpolation {
+  Step,
+  Linear,
+  Cosine,
+  Cubic,
+  Spline,
+  Sinc,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum Oversampling {
+  Off,
+  #[name = "2x"]
+  X2,
+  #[name = "4x"]
+  X4,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum ResamplerQuality {
+  Fast,
+  Sinc,
+  #[name = "Sinc (long)"]
+  SincLong,
+  #[name = "FFT"]
+  Fft,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum Normalization {
+  Off,
+  Peak,
+  Loudness,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum FileSampleRate {
+  #[name = "44.1 kHz"]
+  Hz44100,
+  #[name = "48 kHz"]
+  Hz48000,
+  #[name = "88.2 kHz"]
+  Hz88200,
+  #[name = "96 kHz"]
+  Hz96000,
+}
+
+#[derive(Enum, PartialEq)]
+pub enum BufferLength {
+  #[name = "10 s"]
+  S10,
+  #[name = "30 s"]
+  S30,
+  #[name = "1 min"]
+  M1,
+  #[name = "2 min"]
+  M2,
+  #[name = "5 min"]
+  M5,
+}
+
 #[derive(Params)]
 pub struct TimeWarpParameters {
   #[persist = "editor-state"]
@@ -43,6 +179,15 @@ pub struct TimeWarpParameters {
   #[id = "erase"]
   pub erase: BoolParam,
 
+  #[id = "record_threshold"]
+  pub record_threshold: FloatParam,
+
+  #[id = "reverse"]
+  pub reverse: BoolParam,
+
+  #[id = "tape_speed"]
+  pub tape_speed: EnumParam<TapeSpeed>,
+
   #[id = "scan"]
   pub scan: FloatParam,
 
@@ -97,6 +242,39 @@ pub struct TimeWarpParameters {
   #[id = "feedback"]
   pub feedback: FloatParam,
 
+  #[id = "shimmer"]
+  pub shimmer: FloatParam,
+
+  #[id = "shimmer_interval"]
+  pub shimmer_interval: EnumParam<ShimmerInterval>,
+
+  #[id = "saturation"]
+  pub saturation: EnumParam<Saturation>,
+
+  #[id = "drive"]
+  pub drive: FloatParam,
+
+  #[id = "wow"]
+  pub wow: FloatParam,
+
+  #[id = "flutter"]
+  pub flutter: FloatParam,
+
+  #[id = "filter_placement"]
+  pub filter_placement: EnumParam<FilterPlacement>,
+
+  #[id = "filter_mode"]
+  pub filter_mode: EnumParam<FilterMode>,
+
+  #[id = "filter_slope"]
+  pub filter_slope: EnumParam<FilterSlope>,
+
+  #[id = "filter_cutoff"]
+  pub filter_cutoff: FloatParam,
+
+  #[id = "filter_resonance"]
+  pub filter_resonance: FloatParam,
+
   #[id = "attack"]
   pub attack: FloatParam,
 
@@ -118,22 +296,119 @@ pub struct TimeWarpParameters {
   #[id = "sync_position"]
   pub sync_position: BoolParam,
 
+  #[id = "reverb_size"]
+  pub reverb_size: FloatParam,
+
+  #[id = "reverb_decay"]
+  pub reverb_decay: FloatParam,
+
+  #[id = "reverb_mix"]
+  pub reverb_mix: FloatParam,
+
+  #[id = "reverb_to_delay"]
+  pub reverb_to_delay: BoolParam,
+
+  #[id = "eq_low"]
+  pub eq_low: FloatParam,
+
+  #[id = "eq_mid"]
+  pub eq_mid: FloatParam,
+
+  #[id = "eq_mid_freq"]
+  pub eq_mid_freq: FloatParam,
+
+  #[id = "eq_high"]
+  pub eq_high: FloatParam,
+
   #[id = "dry"]
   pub dry: FloatParam,
 
   #[id = "wet"]
   pub wet: FloatParam,
 
+  #[id = "separate_wet_output"]
+  pub separate_wet_output: BoolParam,
+
+  #[id = "envelope_attack"]
+  pub envelope_attack: FloatParam,
+
+  #[id = "envelope_release"]
+  pub envelope_release: FloatParam,
+
+  #[id = "envelope_amount"]
+  pub envelope_amount: FloatParam,
+
+  #[id = "envelope_destination"]
+  pub envelope_destination: EnumParam<EnvelopeDestination>,
+
+  #[id = "gate_threshold"]
+  pub gate_threshold: FloatParam,
+
+  #[id = "duck_threshold"]
+  pub duck_threshold: FloatParam,
+
+  #[id = "duck_amount"]
+  pub duck_amount: FloatParam,
+
+  #[id = "duck_release"]
+  pub duck_release: FloatParam,
+
+  #[id = "record_source"]
+  pub record_source: EnumParam<InputSource>,
+
+  #[id = "envelope_source"]
+  pub envelope_source: EnumParam<InputSource>,
+
+  #[id = "interpolation"]
+  pub interpolation: EnumParam<Interpolation>,
+
+  #[id = "oversampling"]
+  pub oversampling: EnumParam<Oversampling>,
+
+  #[id = "buffer_length"]
+  pub buffer_length: EnumParam<BufferLength>,
+
+  #[id = "resampler_quality"]
+  pub resampler_quality: EnumParam<ResamplerQuality>,
+
+  #[id = "file_sample_rate"]
+  pub file_sample_rate: EnumParam<FileSampleRate>,
+
+  #[id = "normalization"]
+  pub normalization: EnumParam<Normalization>,
+
+  #[id = "trim_threshold"]
+  pub trim_threshold: FloatParam,
+
+  #[id = "remove_dc"]
+  pub remove_dc: BoolParam,
+
+  #[id = "reverse_sample"]
+  pub reverse_sample: BoolParam,
+
+  #[id = "embed_sample"]
+  pub embed_sample: BoolParam,
+
   #[persist = "file_path"]
   pub file_path: Arc<Mutex<String>>,
 
+  /// The error of the last file that failed to load, which is empty after a successful load.
+  pub file_status: Arc<Mutex<String>>,
+
+  #[persist = "embedded_sample"]
+  pub embedded_sample: Arc<Mutex<EmbeddedSample>>,
+
   #[persist = "max_size"]
   pub max_size: Arc<AtomicF32>,
+
+  /// The length of the current buffer, which limits the displayed time.
+  pub max_delay_time: Arc<AtomicF32>,
 }
 
 impl Default for TimeWarpParameters {
   fn default() -> Self {
     let max_size = Arc::new(AtomicF32::new(MAX_PARAM_DELAY_TIME));
+    let max_delay_time = Arc::new(AtomicF32::new(DEFAULT_MAX_DELAY_TIME));
 
     Self {
       editor_state: editor::default_state(),
@@ -144,6 +419,24 @@ impl Default for TimeWarpParameters {
 
       erase: BoolParam::new("Erase", false),
 
+      record_threshold: FloatParam::new(
+        "Rec. Threshold",
+        -70.,
+        FloatRange::Linear { min: -70., max: 0. },
+      )
+      .with_unit(" dB")
+      .with_value_to_string(Arc::new(move |value| {
+        if value == -70. {
+          "Off".to_string()
+        } else {
+          format!("{:.2}", value)
+        }
+      })),
+
+      reverse: BoolParam::new("Reverse", false),
+
+      tape_speed: EnumParam::new("Tape Speed", TapeSpeed::Normal),
+
       scan: FloatParam::new("Scan", 0., FloatRange::Linear { min: 0., max: 1. })
         .with_unit(" %")
         .with_value_to_string(v2s_f32_percentage(2))
@@ -210,10 +503,10 @@ impl Default for TimeWarpParameters {
         FloatRange::Skewed {
           min: MIN_DELAY_TIME,
           max: MAX_PARAM_DELAY_TIME,
-          factor: 0.3,
+          factor: 0.2,
         },
       )
-      .with_value_to_string(v2s_f32_ms_then_s())
+      .with_value_to_string(v2s_time(max_delay_time.clone()))
       .with_string_to_value(s2v_f32_ms_then_s()),
 
       division: IntParam::new("Division", 15, IntRange::Linear { min: 0, max: 20 })
@@ -259,6 +552,52 @@ impl Default for TimeWarpParameters {
         .with_value_to_string(v2s_f32_percentage(2))
         .with_string_to_value(s2v_f32_percentage()),
 
+      shimmer: FloatParam::new("Shimmer", 0., FloatRange::Linear { min: 0., max: 1. })
+        .with_unit(" %")
+        .with_value_to_string(v2s_f32_percentage(2))
+        .with_string_to_value(s2v_f32_percentage()),
+
+      shimmer_interval: EnumParam::new("Shimmer Interval", ShimmerInterval::OctaveUp),
+
+      saturation: EnumParam::new("Saturation", Saturation::Clip),
+
+      drive: FloatParam::new("Drive", 0., FloatRange::Linear { min: 0., max: 24. })
+        .with_unit(" dB")
+        .with_value_to_string(v2s_f32_rounded(2)),
+
+      wow: FloatParam::new("Wow", 0., FloatRange::Linear { min: 0., max: 1. })
+        .with_unit(" %")
+        .with_value_to_string(v2s_f32_percentage(2))
+        .with_string_to_value(s2v_f32_percentage()),
+
+      flutter: FloatParam::new("Flutter", 0., FloatRange::Linear { min: 0., max: 1. })
+        .with_unit(" %")
+        .with_value_to_string(v2s_f32_percentage(2))
+        .with_string_to_value(s2v_f32_percentage()),
+
+      filter_placement: EnumParam::new("Filter", FilterPlacement::Off),
+
+      filter_mode: EnumParam::new("Filter Mode", FilterMode::Lowpass),
+
+      filter_slope: EnumParam::new("Filter Slope", FilterSlope::Db12),
+
+      filter_cutoff: FloatParam::new(
+        "Cutoff",
+        1000.,
+        FloatRange::Skewed {
+          min: 20.,
+          max: 20000.,
+          factor: 0.2,
+        },
+      )
+      .with_value_to_string(v2s_f32_hz_then_khz(2))
+      .with_string_to_value(s2v_f32_hz_then_khz()),
+
+      filter_resonance: FloatParam::new("Resonance", 0., FloatRange::Linear { min: 0., max: 1. })
+        .with_unit(" %")
+        .with_value_to_string(v2s_f32_percentage(2))
+        .with_string_to_value(s2v_f32_percentage()),
+
       attack: FloatParam::new(
         "Attack",
         1.,
@@ -311,6 +650,75 @@ impl Default for TimeWarpParameters {
         },
       ),
 
+      reverb_size: FloatParam::new("Reverb Size", 0.5, FloatRange::Linear { min: 0., max: 1. })
+        .with_unit(" %")
+        .with_value_to_string(v2s_f32_percentage(2))
+        .with_string_to_value(s2v_f32_percentage()),
+
+      reverb_decay: FloatParam::new(
+        "Reverb Decay",
+        2.,
+        FloatRange::Skewed {
+          min: 0.1,
+          max: 20.,
+          factor: 0.3,
+        },
+      )
+      .with_unit(" s")
+      .with_value_to_string(v2s_f32_rounded(2)),
+
+      reverb_mix: FloatParam::new("Reverb Mix", 0., FloatRange::Linear { min: 0., max: 1. })
+        .with_unit(" %")
+        .with_value_to_string(v2s_f32_percentage(2))
+        .with_string_to_value(s2v_f32_percentage()),
+
+      reverb_to_delay: BoolParam::new("Reverb To Delay", false),
+
+      eq_low: FloatParam::new(
+        "Low",
+        0.,
+        FloatRange::Linear {
+          min: -12.,
+          max: 12.,
+        },
+      )
+      .with_unit(" dB")
+      .with_value_to_string(v2s_f32_rounded(2)),
+
+      eq_mid: FloatParam::new(
+        "Mid",
+        0.,
+        FloatRange::Linear {
+          min: -12.,
+          max: 12.,
+        },
+      )
+      .with_unit(" dB")
+      .with_value_to_string(v2s_f32_rounded(2)),
+
+      eq_mid_freq: FloatParam::new(
+        "Mid Freq",
+        1000.,
+        FloatRange::Skewed {
+          min: 200.,
+          max: 8000.,
+          factor: 0.3,
+        },
+      )
+      .with_value_to_string(v2s_f32_hz_then_khz(2))
+      .with_string_to_value(s2v_f32_hz_then_khz()),
+
+      eq_high: FloatParam::new(
+        "High",
+        0.,
+        FloatRange::Linear {
+          min: -12.,
+          max: 12.,
+        },
+      )
+      .with_unit(" dB")
+      .with_value_to_string(v2s_f32_rounded(2)),
+
       dry: FloatParam::new(
         "Dry",
         0.,
@@ -347,9 +755,130 @@ impl Default for TimeWarpParameters {
         }
       })),
 
+      separate_wet_output: BoolParam::new("Wet To Aux Out", false),
+
+      envelope_attack: FloatParam::new(
+        "Env. Attack",
+        10.,
+        FloatRange::Skewed {
+          min: 0.1,
+          max: 500.,
+          factor: 0.3,
+        },
+      )
+      .with_value_to_string(v2s_f32_ms_then_s())
+      .with_string_to_value(s2v_f32_ms_then_s()),
+
+      envelope_release: FloatParam::new(
+        "Env. Release",
+        250.,
+        FloatRange::Skewed {
+          min: 1.,
+          max: 5000.,
+          factor: 0.3,
+        },
+      )
+      .with_value_to_string(v2s_f32_ms_then_s())
+      .with_string_to_value(s2v_f32_ms_then_s()),
+
+      envelope_amount: FloatParam::new("Env. Amount", 0., FloatRange::Linear { min: -1., max: 1. })
+        .with_unit(" %")
+        .with_value_to_string(v2s_f32_percentage(2))
+        .with_string_to_value(s2v_f32_percentage()),
+
+      envelope_destination: EnumParam::new("Env. Destination", EnvelopeDestination::Density),
+
+      gate_threshold: FloatParam::new("Gate", -70., FloatRange::Linear { min: -70., max: 0. })
+        .with_unit(" dB")
+        .with_value_to_string(Arc::new(move |value| {
+          if value == -70. {
+            "Off".to_string()
+          } else {
+            format!("{:.2}", value)
+          }
+        })),
+
+      duck_threshold: FloatParam::new(
+        "Duck Threshold",
+        -70.,
+        FloatRange::Linear { min: -70., max: 0. },
+      )
+      .with_unit(" dB")
+      .with_value_to_string(Arc::new(move |value| {
+        if value == -70. {
+          "Off".to_string()
+        } else {
+          format!("{:.2}", value)
+        }
+      })),
+
+      duck_amount: FloatParam::new("Duck Amount", 0., FloatRange::Linear { min: 0., max: 1. })
+        .with_unit(" %")
+        .with_value_to_string(v2s_f32_percentage(2))
+        .with_string_to_value(s2v_f32_percentage()),
+
+      duck_release: FloatParam::new(
+        "Duck Release",
+        250.,
+        FloatRange::Skewed {
+          min: 10.,
+          max: 5000.,
+          factor: 0.3,
+        },
+      )
+      .with_unit(" ms")
+      .with_value_to_string(v2s_f32_rounded(2)),
+
+      record_source: EnumParam::new("Record Source", InputSource::Main),
+
+      envelope_source: EnumParam::new("Envelope Source", InputSource::Main),
+
+      interpolation: EnumParam::new("Interpolation", Interpolation::Linear),
+
+      oversampling: EnumParam::new("Oversampling", Oversampling::Off),
+
+      buffer_length: EnumParam::new("Buffer Length", BufferLength::M1).non_automatable(),
+
+      resampler_quality: EnumParam::new("Resampler", ResamplerQuality::Fft).non_automatable(),
+
+      file_sample_rate: EnumParam::new("File Sample Rate", FileSampleRate::Hz44100)
+        .non_automatable(),
+
+      normalization: EnumParam::new("Normalize", Normalization::Off).non_automatable(),
+
+      trim_threshold: FloatParam::new(
+        "Trim Threshold",
+        -90.,
+        FloatRange::Linear {
+          min: -90.,
+          max: -20.,
+        },
+      )
+      .with_unit(" dB")
+      .with_value_to_string(Arc::new(move |value| {
+        if value == -90. {
+          "Off".to_string()
+        } else {
+          format!("{:.2}", value)
+        }
+      }))
+      .non_automatable(),
+
+      remove_dc: BoolParam::new("Remove DC", false).non_automatable(),
+
+      reverse_sample: BoolParam::new("Reverse Sample", false).non_automatable(),
+
+      embed_sample: BoolParam::new("Embed Sample", false).non_automatable(),
+
       file_path: Arc::new(Mutex::new("".to_string())),
 
+      file_status: Arc::new(Mutex::new("".to_string())),
+
+      embedded_sample: Arc::new(Mutex::new(EmbeddedSample::default())),
+
       max_size,
+
+      max_delay_time,
     }
   }
 }
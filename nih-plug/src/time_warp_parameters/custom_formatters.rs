@@ -18,6 +18,12 @@ pub fn v2s_f32_ms_then_s() -> Arc<dyn Fn(f32) -> String + Send + Sync> {
   })
 }
 
+/// Shows the time that's actually used, which is limited by the buffer length.
+pub fn v2s_time(max_delay_time: Arc<AtomicF32>) -> Arc<dyn Fn(f32) -> String + Send + Sync> {
+  let v2s = v2s_f32_ms_then_s();
+  Arc::new(move |value| v2s(value.min(max_delay_time.load(Ordering::Relaxed))))
+}
+
 pub fn s2v_f32_ms_then_s() -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
   Arc::new(move |string| {
     let time_segment = string.trim().to_ascii_lowercase();
@@ -0,0 +1,32 @@
+use {
+  base64::{engine::general_purpose::STANDARD, Engine},
+  serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer},
+  std::sync::Arc,
+};
+
+/// The processed sample, encoded as FLAC and stored in the plugin state, so a preset doesn't
+/// depend on the file path. It's serialized as base64, which is much smaller than a list of bytes.
+#[derive(Clone, Default)]
+pub struct EmbeddedSample(pub Option<Arc<[u8]>>);
+
+impl Serialize for EmbeddedSample {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    match &self.0 {
+      Some(bytes) => serializer.serialize_str(&STANDARD.encode(bytes)),
+      None => serializer.serialize_str(""),
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for EmbeddedSample {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    if encoded.is_empty() {
+      return Ok(Self(None));
+    }
+    STANDARD
+      .decode(encoded)
+      .map(|bytes| Self(Some(bytes.into())))
+      .map_err(D::Error::custom)
+  }
+}
@@ -1,40 +1,60 @@
+use crate::time_warp_parameters::{
+  embedded_sample::EmbeddedSample, FileSampleRate as ParamFileSampleRate,
+  Normalization as ParamNormalization, ResamplerQuality as ParamResamplerQuality,
+  TimeWarpParameters,
+};
 use crossbeam_channel::{Receiver, Sender}; // TODO: check other crates like omange, ringbuf or rtrb as an alternative
-use nih_plug::prelude::AtomicF32;
-use std::sync::{
-  atomic::{AtomicUsize, Ordering},
-  Arc, Mutex,
+use nih_plug::{nih_log, prelude::AtomicF32};
+use std::{
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
+  time::Duration,
+};
+use time_warp::{
+  AudioFile, AudioFileProcessingError, AudioFileProcessor, DelayLine, FileStream, Normalization,
+  ResamplerQuality, SampleProcessing, StreamWindow, TimeWarp,
 };
-use time_warp::{AudioFileData, AudioFileProcessor};
+
+// the response to a window request waits this long for room in the channel, because it carries
+// the file stream
+const FILE_WINDOW_SEND_TIMEOUT: Duration = Duration::from_millis(500);
 
 pub enum WorkerRequest {
   LoadFile(String, bool),
-  FlushBuffer,
+  FlushBuffer(f32),
+  ResizeBuffer(f32),
+  EmbedSample,
+  ReadFileWindow(FileStream, f32),
 }
 
 pub enum WorkerResponseData {
-  LoadFile(AudioFileData),
-  FlushBuffer(Vec<f32>),
+  LoadFile(DelayLine, f32),
+  StreamFile(FileStream, StreamWindow),
+  FlushBuffer(DelayLine, f32),
+  ResizeBuffer(DelayLine, f32),
+  ReadFileWindow(FileStream, StreamWindow),
+  // the stream is handed back, so the next window request can retry
+  ReadFileWindowError(FileStream),
 }
 
 #[derive(Clone)]
 pub struct Worker {
   sample_rate: Arc<AtomicF32>,
-  file_path_param: Arc<Mutex<String>>,
+  params: Arc<TimeWarpParameters>,
   sender: Sender<WorkerResponseData>,
   receiver: Receiver<WorkerResponseData>,
   delay_line_size: Arc<AtomicUsize>,
 }
 
 impl Worker {
-  pub fn new(
-    sample_rate: f32,
-    file_path_param: Arc<Mutex<String>>,
-    delay_line_size: usize,
-  ) -> Self {
-    let (sender, receiver) = crossbeam_channel::bounded(1);
+  pub fn new(sample_rate: f32, params: Arc<TimeWarpParameters>, delay_line_size: usize) -> Self {
+    // a resize is followed by reloading the sample, so both responses can be queued
+    let (sender, receiver) = crossbeam_channel::bounded(2);
     Self {
       sample_rate: Arc::new(AtomicF32::new(sample_rate)),
-      file_path_param,
+      params,
       sender,
       receiver,
       delay_line_size: Arc::new(AtomicUsize::new(delay_line_size)),
@@ -52,38 +72,202 @@ impl Worker {
         if file_path.is_empty() {
           return;
         }
-        let audio_file_data = match AudioFileProcessor::new(
-          self.sample_rate.load(Ordering::Relaxed),
-          self.delay_line_size.load(Ordering::Relaxed),
-        )
-        .read(&file_path)
-        {
-          Ok(data) => data,
-          Err(_) => {
+        let mut audio_file_processor = self.create_audio_file_processor();
+        let embedded_sample = if should_update_file_path {
+          None
+        } else {
+          self.params.embedded_sample.lock().unwrap().0.clone()
+        };
+        // a restored preset reads its embedded sample, so it doesn't depend on the file path
+        let result = match embedded_sample {
+          Some(bytes) => audio_file_processor.open_embedded_sample(bytes),
+          None => audio_file_processor.open(&file_path),
+        };
+        // a restored sample keeps what's embedded, only a new file replaces it
+        let should_embed = should_update_file_path && self.params.embed_sample.value();
+        let mut embed_result = Ok(None);
+        let response_data = match result {
+          Ok(AudioFile::Buffered(data)) => {
+            nih_log!("Loaded {}: {}", file_path, data.sample_rate_conversion);
+            if should_embed {
+              embed_result = data.encode().map(|bytes| Some(Arc::from(bytes)));
+            }
+            // the mip map is built here, so the audio thread only swaps the delay line in
+            let delay_line = TimeWarp::create_delay_line_from_values(
+              data.samples,
+              data.duration_in_samples,
+              self.sample_rate.load(Ordering::Relaxed),
+            );
+            WorkerResponseData::LoadFile(delay_line, data.duration_in_ms)
+          }
+          // files that don't fit in the delay line are streamed, starting with their first part
+          Ok(AudioFile::Streamed(mut file_stream)) => match file_stream.read_window(0.) {
+            Ok(window) => {
+              if should_embed {
+                embed_result = Err(AudioFileProcessingError::EmbedSizeError);
+              }
+              nih_log!(
+                "Streaming {}: {}",
+                file_path,
+                file_stream.get_sample_rate_conversion()
+              );
+              WorkerResponseData::StreamFile(file_stream, window)
+            }
+            Err(error) => {
+              self.report_error(&file_path, error);
+              return;
+            }
+          },
+          Err(error) => {
+            self.report_error(&file_path, error);
             return;
           }
         };
+        self.params.file_status.lock().unwrap().clear();
 
-        match self
-          .sender
-          .try_send(WorkerResponseData::LoadFile(audio_file_data))
-        {
+        match self.sender.try_send(response_data) {
           Ok(_) => {
             if should_update_file_path {
               // should not replace file_path on initial load
-              *self.file_path_param.lock().unwrap() = file_path.clone();
+              *self.params.file_path.lock().unwrap() = file_path.clone();
+              self.store_embedded_sample(&file_path, embed_result);
             }
           }
           _ => {}
         }
       }
-      WorkerRequest::FlushBuffer => {
-        let empty_buffer = vec![0.; self.delay_line_size.load(Ordering::Relaxed)];
+      WorkerRequest::FlushBuffer(max_delay_time) => {
+        *self.params.embedded_sample.lock().unwrap() = EmbeddedSample::default();
+        // a new delay line is sent, because the current one can be resized before this arrives
+        let delay_line =
+          TimeWarp::create_delay_line(self.sample_rate.load(Ordering::Relaxed), max_delay_time);
         self
           .sender
-          .try_send(WorkerResponseData::FlushBuffer(empty_buffer))
+          .try_send(WorkerResponseData::FlushBuffer(delay_line, max_delay_time))
           .ok();
       }
+      WorkerRequest::ResizeBuffer(max_delay_time) => {
+        let delay_line =
+          TimeWarp::create_delay_line(self.sample_rate.load(Ordering::Relaxed), max_delay_time);
+        let size = delay_line.get_size();
+        if self
+          .sender
+          .try_send(WorkerResponseData::ResizeBuffer(delay_line, max_delay_time))
+          .is_err()
+        {
+          return;
+        }
+        self.delay_line_size.store(size, Ordering::Relaxed);
+
+        let file_path = self.params.file_path.lock().unwrap().clone();
+        self.handle_task(WorkerRequest::LoadFile(file_path, false));
+      }
+      WorkerRequest::EmbedSample => {
+        if !self.params.embed_sample.value() {
+          *self.params.embedded_sample.lock().unwrap() = EmbeddedSample::default();
+        } else if self.params.embedded_sample.lock().unwrap().0.is_none() {
+          let file_path = self.params.file_path.lock().unwrap().clone();
+          self.embed_file(&file_path);
+        }
+      }
+      WorkerRequest::ReadFileWindow(mut file_stream, position) => {
+        let response_data = match file_stream.read_window(position) {
+          Ok(window) => WorkerResponseData::ReadFileWindow(file_stream, window),
+          Err(error) => {
+            let file_path = self.params.file_path.lock().unwrap().clone();
+            self.report_error(&file_path, error);
+            WorkerResponseData::ReadFileWindowError(file_stream)
+          }
+        };
+        self
+          .sender
+          .send_timeout(response_data, FILE_WINDOW_SEND_TIMEOUT)
+          .ok();
+      }
+    }
+  }
+
+  /// Reads and processes the file again, to embed it when embedding is turned on.
+  fn embed_file(&self, file_path: &str) {
+    if file_path.is_empty() {
+      return;
+    }
+    let result = match self.create_audio_file_processor().open(file_path) {
+      Ok(AudioFile::Buffered(data)) => data.encode().map(|bytes| Some(Arc::from(bytes))),
+      // a streamed file is longer than the delay line, which is too large to embed
+      Ok(AudioFile::Streamed(_)) => Err(AudioFileProcessingError::EmbedSizeError),
+      Err(error) => Err(error),
+    };
+    self.store_embedded_sample(file_path, result);
+  }
+
+  /// Stores the encoded sample in the plugin state, or clears it and reports why it's not embedded.
+  fn store_embedded_sample(
+    &self,
+    file_path: &str,
+    result: Result<Option<Arc<[u8]>>, AudioFileProcessingError>,
+  ) {
+    let bytes = match result {
+      Ok(bytes) => bytes,
+      Err(error) => {
+        nih_log!("Failed to embed {}: {}", file_path, error);
+        *self.params.file_status.lock().unwrap() = error.to_string();
+        None
+      }
+    };
+    *self.params.embedded_sample.lock().unwrap() = EmbeddedSample(bytes);
+  }
+
+  fn create_audio_file_processor(&self) -> AudioFileProcessor {
+    AudioFileProcessor::new(
+      self.sample_rate.load(Ordering::Relaxed),
+      self.delay_line_size.load(Ordering::Relaxed),
+      Self::map_resampler_quality(self.params.resampler_quality.value()),
+      Some(Self::map_file_sample_rate(
+        self.params.file_sample_rate.value(),
+      )),
+      self.get_sample_processing(),
+    )
+  }
+
+  fn report_error(&self, file_path: &str, error: AudioFileProcessingError) {
+    nih_log!("Failed to load {}: {}", file_path, error);
+    *self.params.file_status.lock().unwrap() = error.to_string();
+  }
+
+  fn map_resampler_quality(resampler_quality: ParamResamplerQuality) -> ResamplerQuality {
+    match resampler_quality {
+      ParamResamplerQuality::Fast => ResamplerQuality::Fast,
+      ParamResamplerQuality::Sinc => ResamplerQuality::Sinc(64),
+      ParamResamplerQuality::SincLong => ResamplerQuality::Sinc(256),
+      ParamResamplerQuality::Fft => ResamplerQuality::Fft,
+    }
+  }
+
+  fn map_file_sample_rate(file_sample_rate: ParamFileSampleRate) -> usize {
+    match file_sample_rate {
+      ParamFileSampleRate::Hz44100 => 44100,
+      ParamFileSampleRate::Hz48000 => 48000,
+      ParamFileSampleRate::Hz88200 => 88200,
+      ParamFileSampleRate::Hz96000 => 96000,
+    }
+  }
+
+  fn get_sample_processing(&self) -> SampleProcessing {
+    let trim_threshold = self.params.trim_threshold.value();
+    SampleProcessing {
+      normalization: match self.params.normalization.value() {
+        ParamNormalization::Off => Normalization::Off,
+        ParamNormalization::Peak => Normalization::Peak,
+        ParamNormalization::Loudness => Normalization::Loudness,
+      },
+      trim_threshold: if trim_threshold == -90. {
+        None
+      } else {
+        Some(trim_threshold)
+      },
+      remove_dc: self.params.remove_dc.value(),
+      reverse: self.params.reverse_sample.value(),
     }
   }
 
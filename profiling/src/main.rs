@@ -1,10 +1,10 @@
 mod utils;
-use time_warp::{Notes, Params, TimeWarp};
+use time_warp::{Notes, Params, TimeWarp, DEFAULT_MAX_DELAY_TIME};
 use utils::generate_signal;
 
 fn main() {
-  let mut time_warp = TimeWarp::new(44100.);
-  let mut params = Params::new(44100.);
+  let mut time_warp = TimeWarp::new(44100., DEFAULT_MAX_DELAY_TIME);
+  let mut params = Params::new(44100., DEFAULT_MAX_DELAY_TIME);
   let mut notes = Notes::new();
   params.set(
     true,
@@ -32,12 +32,70 @@ fn main() {
     true,
     0.,
     0.,
+    &time_warp::Settings {
+      record: time_warp::RecordSettings {
+        threshold: -70.,
+        source: time_warp::InputSource::Main,
+      },
+      tape: time_warp::TapeSettings {
+        speed: time_warp::TapeSpeed::Normal,
+        reverse: false,
+      },
+      shimmer: time_warp::ShimmerSettings {
+        amount: 0.,
+        interval: time_warp::ShimmerInterval::OctaveUp,
+      },
+      saturation: time_warp::SaturationSettings {
+        saturation: time_warp::Saturation::Clip,
+        drive: 0.,
+      },
+      wow_flutter: time_warp::WowFlutterSettings {
+        wow: 0.,
+        flutter: 0.,
+      },
+      filter: time_warp::FilterSettings {
+        placement: time_warp::FilterPlacement::Off,
+        mode: time_warp::FilterMode::Lowpass,
+        slope: time_warp::FilterSlope::Db12,
+        cutoff: 1000.,
+        resonance: 0.,
+      },
+      reverb: time_warp::ReverbSettings {
+        size: 0.5,
+        decay: 2.,
+        mix: 0.,
+        to_delay: false,
+      },
+      eq: time_warp::EqSettings {
+        low: 0.,
+        mid: 0.,
+        mid_freq: 1000.,
+        high: 0.,
+      },
+      envelope: time_warp::EnvelopeSettings {
+        attack: 10.,
+        release: 250.,
+        amount: 0.,
+        destination: time_warp::EnvelopeDestination::Density,
+        gate_threshold: -70.,
+        source: time_warp::InputSource::Main,
+      },
+      ducking: time_warp::DuckingSettings {
+        threshold: -70.,
+        amount: 0.,
+        release: 250.,
+      },
+      quality: time_warp::QualitySettings {
+        interpolation: time_warp::Interpolation::Linear,
+        oversampling: time_warp::Oversampling::Off,
+      },
+    },
     512,
   );
   time_warp.get_filter().set_cutoff_frequencies(200., 3000.);
 
   loop {
     let input = (generate_signal(), generate_signal());
-    time_warp.process(input, &mut params, &mut notes.get_notes());
+    time_warp.process(input, None, &mut params, &mut notes.get_notes());
   }
 }
@@ -1,17 +1,30 @@
 mod fade_in_out;
+mod file_stream;
+mod flac_encoder;
+mod resampling;
+mod sample_processing;
 use {
   crate::{shared::float_ext::FloatExt, FADE_TIME, MIN_DELAY_TIME},
   fade_in_out::FadeInOut,
-  rubato::{audioadapter_buffers::direct::InterleavedSlice, Fft, FixedSync, Resampler},
-  std::{fs::File, path::Path},
+  std::{
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
+    path::Path,
+  },
   symphonia::core::{
-    codecs::audio::AudioDecoderOptions,
-    formats::{probe::Hint, FormatOptions, TrackType},
-    io::MediaSourceStream,
+    codecs::audio::{AudioDecoder, AudioDecoderOptions},
+    formats::{probe::Hint, FormatOptions, FormatReader, SeekMode, SeekTo, TrackType},
+    io::{MediaSource, MediaSourceStream},
     meta::MetadataOptions,
+    units::Timestamp,
   },
   thiserror::Error,
 };
+pub use {
+  file_stream::{FileStream, StreamWindow},
+  resampling::{ResamplerQuality, SampleRateConversion},
+  sample_processing::{Normalization, SampleProcessing},
+};
 
 #[derive(Debug, Error)]
 pub enum AudioFileProcessingError {
@@ -41,12 +54,41 @@ pub enum AudioFileProcessingError {
 
   #[error("File duration is too short.")]
   DurationTooShortError,
+
+  #[error("The sample is too large to embed.")]
+  EmbedSizeError,
 }
 
+/// Samples that are larger than this once they're encoded aren't embedded, because the plugin
+/// state ends up in the project file of the host.
+const MAX_EMBEDDED_SAMPLE_SIZE: usize = 16 * 1024 * 1024;
+
 pub struct AudioFileData {
   pub samples: Vec<f32>,
   pub duration_in_samples: usize,
   pub duration_in_ms: f32,
+  pub sample_rate_conversion: SampleRateConversion,
+}
+
+impl AudioFileData {
+  /// Encodes the processed sample as FLAC, so it can be embedded in the plugin state. It should be
+  /// read back with `AudioFileProcessor::open_embedded_sample`, which doesn't process it again.
+  pub fn encode(&self) -> Result<Vec<u8>, AudioFileProcessingError> {
+    let bytes = flac_encoder::encode(
+      &self.samples[..self.duration_in_samples],
+      self.sample_rate_conversion.host_sample_rate,
+    );
+    if bytes.len() > MAX_EMBEDDED_SAMPLE_SIZE {
+      return Err(AudioFileProcessingError::EmbedSizeError);
+    }
+    Ok(bytes)
+  }
+}
+
+/// A file that fits in the delay line is read at once, a longer one is streamed from disk.
+pub enum AudioFile {
+  Buffered(AudioFileData),
+  Streamed(FileStream),
 }
 
 #[derive(Clone)]
@@ -54,27 +96,248 @@ pub struct AudioFileProcessor {
   host_sample_rate: usize,
   max_size: usize,
   fade_in_out: FadeInOut,
+  resampler_quality: ResamplerQuality,
+  fallback_sample_rate: Option<usize>,
+  sample_processing: SampleProcessing,
+  is_processed: bool,
 }
 
 impl AudioFileProcessor {
-  pub fn new(sample_rate: f32, max_size: usize) -> Self {
+  /// The `fallback_sample_rate` is used for files without a sample rate, which are rejected if
+  /// it's `None`. A streamed file gets the `sample_processing` too, which is worked out from the
+  /// whole file when the stream is opened.
+  pub fn new(
+    sample_rate: f32,
+    max_size: usize,
+    resampler_quality: ResamplerQuality,
+    fallback_sample_rate: Option<usize>,
+    sample_processing: SampleProcessing,
+  ) -> Self {
     Self {
       host_sample_rate: sample_rate as usize,
       max_size,
       fade_in_out: FadeInOut::new(sample_rate, FADE_TIME as f32),
+      resampler_quality,
+      fallback_sample_rate,
+      sample_processing,
+      is_processed: false,
     }
   }
 
+  /// Reads the whole file, cut off at the max size.
   pub fn read<'a, P: AsRef<Path>>(
     &mut self,
     file_path: P,
   ) -> Result<AudioFileData, AudioFileProcessingError> {
+    let source_file = SourceFile::open(file_path, self.fallback_sample_rate)?;
+    self.read_source_file(source_file)
+  }
+
+  /// Reads the whole file from an encoded file in memory, cut off at the max size.
+  pub fn read_from_bytes<B: AsRef<[u8]> + Send + Sync + 'static>(
+    &mut self,
+    bytes: B,
+  ) -> Result<AudioFileData, AudioFileProcessingError> {
+    let source_file = SourceFile::new(Box::new(Cursor::new(bytes)), self.fallback_sample_rate)?;
+    self.read_source_file(source_file)
+  }
+
+  /// Reads the whole file from any seekable source, cut off at the max size.
+  pub fn read_from_source<S: Read + Seek + Send + Sync + 'static>(
+    &mut self,
+    source: S,
+  ) -> Result<AudioFileData, AudioFileProcessingError> {
+    let source_file = SourceFile::new(Box::new(SeekableSource(source)), self.fallback_sample_rate)?;
+    self.read_source_file(source_file)
+  }
+
+  /// Takes mono samples that are already decoded, and processes them like the samples of a file.
+  pub fn read_from_samples(
+    &mut self,
+    samples: Vec<f32>,
+    sample_rate: usize,
+  ) -> Result<AudioFileData, AudioFileProcessingError> {
+    self.process_samples(samples, sample_rate, false)
+  }
+
+  /// Reads the file if it fits in the max size, or opens it for streaming otherwise.
+  pub fn open<P: AsRef<Path>>(
+    &mut self,
+    file_path: P,
+  ) -> Result<AudioFile, AudioFileProcessingError> {
+    let source_file = SourceFile::open(file_path, self.fallback_sample_rate)?;
+    self.open_source_file(source_file)
+  }
+
+  /// Like `open`, but for an encoded file in memory.
+  pub fn open_from_bytes<B: AsRef<[u8]> + Send + Sync + 'static>(
+    &mut self,
+    bytes: B,
+  ) -> Result<AudioFile, AudioFileProcessingError> {
+    let source_file = SourceFile::new(Box::new(Cursor::new(bytes)), self.fallback_sample_rate)?;
+    self.open_source_file(source_file)
+  }
+
+  /// Like `open_from_bytes`, for a sample that was encoded by `AudioFileData::encode`. The sample
+  /// processing and the fades aren't applied again, because the sample is already processed.
+  pub fn open_embedded_sample<B: AsRef<[u8]> + Send + Sync + 'static>(
+    &mut self,
+    bytes: B,
+  ) -> Result<AudioFile, AudioFileProcessingError> {
+    self.sample_processing = SampleProcessing::default();
+    self.is_processed = true;
+    self.open_from_bytes(bytes)
+  }
+
+  fn open_source_file(
+    &mut self,
+    source_file: SourceFile,
+  ) -> Result<AudioFile, AudioFileProcessingError> {
+    let duration_in_samples =
+      source_file.duration as f64 * self.host_sample_rate as f64 / source_file.sample_rate as f64;
+    if duration_in_samples as usize > self.max_size {
+      Ok(AudioFile::Streamed(FileStream::new(
+        source_file,
+        self.clone(),
+      )?))
+    } else {
+      Ok(AudioFile::Buffered(self.read_source_file(source_file)?))
+    }
+  }
+
+  fn read_source_file(
+    &mut self,
+    mut source_file: SourceFile,
+  ) -> Result<AudioFileData, AudioFileProcessingError> {
+    let mut samples: Vec<f32> = Default::default();
+    while source_file.decode_next_packet(&mut samples)?.is_some() {}
+
+    self.process_samples(
+      samples,
+      source_file.sample_rate,
+      source_file.is_sample_rate_assumed,
+    )
+  }
+
+  /// Resamples the decoded samples, applies the sample processing and fills the buffer.
+  fn process_samples(
+    &mut self,
+    samples: Vec<f32>,
+    sample_rate: usize,
+    is_sample_rate_assumed: bool,
+  ) -> Result<AudioFileData, AudioFileProcessingError> {
+    let mut samples = self.resample(samples, sample_rate)?;
+    self.sample_processing.process(&mut samples);
+    // trimming silence can leave too little of the file
+    if (samples.len() as f32).sampstoms(self.host_sample_rate as f32) < MIN_DELAY_TIME {
+      return Err(AudioFileProcessingError::DurationTooShortError);
+    }
+
+    let sample_rate_conversion =
+      self.get_sample_rate_conversion(sample_rate, is_sample_rate_assumed);
+    // an embedded sample already has its fades, unless it's cut off now
+    let should_fade_out = !self.is_processed || samples.len() > self.max_size;
+    Ok(self.fill_buffer(
+      samples,
+      sample_rate_conversion,
+      !self.is_processed,
+      should_fade_out,
+    ))
+  }
+
+  fn get_sample_rate_conversion(
+    &self,
+    file_sample_rate: usize,
+    is_sample_rate_assumed: bool,
+  ) -> SampleRateConversion {
+    SampleRateConversion {
+      file_sample_rate,
+      host_sample_rate: self.host_sample_rate,
+      resampler_quality: if file_sample_rate == self.host_sample_rate {
+        None
+      } else {
+        Some(self.resampler_quality)
+      },
+      is_sample_rate_assumed,
+    }
+  }
+
+  /// Resample if the file samplerate does not match the host samplerate
+  fn resample(
+    &self,
+    samples: Vec<f32>,
+    file_sample_rate: usize,
+  ) -> Result<Vec<f32>, AudioFileProcessingError> {
+    if file_sample_rate == self.host_sample_rate {
+      return Ok(samples);
+    }
+
+    resampling::resample(
+      &samples,
+      file_sample_rate,
+      self.host_sample_rate,
+      self.resampler_quality,
+    )
+  }
+
+  /// Calculate file duration (capped at the max buffer size), apply fades and pad the buffer.
+  fn fill_buffer(
+    &mut self,
+    mut samples: Vec<f32>,
+    sample_rate_conversion: SampleRateConversion,
+    should_fade_in: bool,
+    should_fade_out: bool,
+  ) -> AudioFileData {
+    let duration_in_samples = samples.len().min(self.max_size);
+    if should_fade_in {
+      self
+        .fade_in_out
+        .fade_in(&mut samples[..duration_in_samples]);
+    }
+    if should_fade_out {
+      self
+        .fade_in_out
+        .fade_out(&mut samples[..duration_in_samples]);
+    }
+    let duration_in_ms = (duration_in_samples as f32).sampstoms(self.host_sample_rate as f32);
+    samples.resize(self.max_size, 0.); // Pad the buffer so it's full
+
+    AudioFileData {
+      samples,
+      duration_in_samples,
+      duration_in_ms,
+      sample_rate_conversion,
+    }
+  }
+}
+
+/// The default audio track of a file, with a decoder for its packets.
+struct SourceFile {
+  format: Box<dyn FormatReader>,
+  decoder: Box<dyn AudioDecoder>,
+  track_id: u32,
+  sample_rate: usize,
+  is_sample_rate_assumed: bool,
+  duration: usize,
+  sample_buf: Vec<f32>,
+}
+
+impl SourceFile {
+  fn open<P: AsRef<Path>>(
+    file_path: P,
+    fallback_sample_rate: Option<usize>,
+  ) -> Result<Self, AudioFileProcessingError> {
     // Create a media source. Note that the MediaSource trait is automatically implemented for File,
     // among other types.
-    let file = Box::new(File::open(file_path)?);
+    Self::new(Box::new(File::open(file_path)?), fallback_sample_rate)
+  }
 
+  fn new(
+    source: Box<dyn MediaSource>,
+    fallback_sample_rate: Option<usize>,
+  ) -> Result<Self, AudioFileProcessingError> {
     // Create the media source stream using the boxed media source from above.
-    let mss = MediaSourceStream::new(file, Default::default());
+    let mss = MediaSourceStream::new(source, Default::default());
 
     // Create a hint to help the format registry guess what format reader is appropriate. In this
     // example we'll leave it empty.
@@ -86,7 +349,7 @@ impl AudioFileProcessor {
     let dec_opts: AudioDecoderOptions = Default::default();
 
     // Probe the media source stream for a format.
-    let mut format = symphonia::default::get_probe().probe(&hint, mss, fmt_opts, meta_opts)?;
+    let format = symphonia::default::get_probe().probe(&hint, mss, fmt_opts, meta_opts)?;
 
     // Get the default audio track.
     let track = format
@@ -101,76 +364,96 @@ impl AudioFileProcessor {
       .ok_or_else(|| {
         AudioFileProcessingError::ReadError("No audio codec parameters found.".to_string())
       })?;
-    let mut decoder =
-      symphonia::default::get_codecs().make_audio_decoder(audio_params, &dec_opts)?;
+    let decoder = symphonia::default::get_codecs().make_audio_decoder(audio_params, &dec_opts)?;
 
     // Store the track identifier, we'll use it to filter packets.
     let track_id = track.id;
 
-    // Read the track samplerate
-    let file_sample_rate = track
-      .codec_params
-      .as_ref()
-      .and_then(|params| {
-        params
-          .audio()
-          .and_then(|audio_params| audio_params.sample_rate.map(|sr| sr as usize))
-      })
+    // Read the track samplerate, or assume the fallback samplerate if it's missing
+    let is_sample_rate_assumed = audio_params.sample_rate.is_none();
+    let sample_rate = audio_params
+      .sample_rate
+      .map(|sr| sr as usize)
+      .or(fallback_sample_rate)
       .ok_or(AudioFileProcessingError::SampleRateError)?;
 
-    // Read the track duration in milliseconds
-    let file_duration_in_ms = track
+    // Read the track duration in samples
+    let duration = track
       .duration
-      .ok_or(AudioFileProcessingError::DurationReadError)
-      .map(|dur| (dur.get() as f32).sampstoms(file_sample_rate as f32))?;
+      .ok_or(AudioFileProcessingError::DurationReadError)?
+      .get() as usize;
     // Return error if the file duration is too short
-    if file_duration_in_ms < MIN_DELAY_TIME {
+    if (duration as f32).sampstoms(sample_rate as f32) < MIN_DELAY_TIME {
       return Err(AudioFileProcessingError::DurationTooShortError);
     }
 
-    let chunk_size = 1024;
-    let mut samples: Vec<f32> = Default::default();
-    let mut sample_buf: Vec<f32> = Default::default();
-    let mut resampler: Option<Fft<f32>> = None;
-
-    while let Some(packet) = match format.next_packet() {
-      Ok(p) => p,
-      Err(symphonia::core::errors::Error::IoError(e)) => {
-        if e.kind() == std::io::ErrorKind::UnexpectedEof {
-          None
-        } else {
-          return Err(AudioFileProcessingError::SymphoniaError(
-            symphonia::core::errors::Error::IoError(e),
-          ));
+    Ok(Self {
+      format,
+      decoder,
+      track_id,
+      sample_rate,
+      is_sample_rate_assumed,
+      duration,
+      sample_buf: Vec::new(),
+    })
+  }
+
+  /// Moves to a position before `frame`, from where the next packets are decoded.
+  fn seek(&mut self, frame: usize) -> Result<(), AudioFileProcessingError> {
+    self.format.seek(
+      SeekMode::Accurate,
+      SeekTo::Timestamp {
+        ts: Timestamp::new(frame as i64),
+        track_id: self.track_id,
+      },
+    )?;
+    self.decoder.reset();
+    Ok(())
+  }
+
+  /// Appends the next packet of the track to `samples` as mono samples and returns the frame it
+  /// starts at, or `None` at the end of the file.
+  fn decode_next_packet(
+    &mut self,
+    samples: &mut Vec<f32>,
+  ) -> Result<Option<i64>, AudioFileProcessingError> {
+    loop {
+      let packet = match self.format.next_packet() {
+        Ok(Some(packet)) => packet,
+        Ok(None) => return Ok(None),
+        Err(symphonia::core::errors::Error::IoError(e)) => {
+          if e.kind() == std::io::ErrorKind::UnexpectedEof {
+            return Ok(None);
+          } else {
+            return Err(AudioFileProcessingError::SymphoniaError(
+              symphonia::core::errors::Error::IoError(e),
+            ));
+          }
         }
-      }
-      Err(e) => return Err(AudioFileProcessingError::SymphoniaError(e)),
-    } {
+        Err(e) => return Err(AudioFileProcessingError::SymphoniaError(e)),
+      };
+
       // If the packet does not belong to the selected track, skip it.
-      if packet.track_id != track_id {
+      if packet.track_id != self.track_id {
         continue;
       }
 
-      // Decode the packet into audio samples, ignoring any decode errors.
-      let audio_buf = decoder.decode(&packet)?;
-
-      // The decoded audio samples may now be accessed via the generic audio buffer
-      // returned by the decoder. You may match on the buffer to access a sample-format
-      // specific buffer, or use generic routines to copy out the audio samples in the
-      // desired sample format.
+      // Decode the packet into audio samples.
+      let audio_buf = self.decoder.decode(&packet)?;
 
       // Copy the decoded audio buffer into the sample buffer in an interleaved format.
-      sample_buf.resize(audio_buf.samples_interleaved(), 0.);
-      audio_buf.copy_to_slice_interleaved(&mut sample_buf);
+      self.sample_buf.resize(audio_buf.samples_interleaved(), 0.);
+      audio_buf.copy_to_slice_interleaved(&mut self.sample_buf);
 
       // Convert stereo samples to mono samples and write the results
       match audio_buf.spec().channels().count() {
         1 => {
-          samples.extend_from_slice(&sample_buf);
+          samples.extend_from_slice(&self.sample_buf);
         }
         2 => {
           samples.extend(
-            sample_buf
+            self
+              .sample_buf
               .chunks_exact(2)
               .map(|chunk| (chunk[0] + chunk[1]) * 0.5),
           );
@@ -181,56 +464,35 @@ impl AudioFileProcessor {
           ));
         }
       };
+
+      return Ok(Some(packet.pts.get()));
     }
+  }
+}
 
-    // Resample if the file samplerate does not match the host samplerate
-    if file_sample_rate != self.host_sample_rate {
-      // Initialize resampler
-      if resampler.is_none() {
-        resampler = Some(Fft::new(
-          file_sample_rate,
-          self.host_sample_rate,
-          chunk_size,
-          2, // number of FFT blocks per processing call
-          1,
-          FixedSync::Both,
-        )?);
-      }
+/// Makes any `Read + Seek` source usable for symphonia, which only implements `MediaSource` for
+/// files and cursors.
+struct SeekableSource<S>(S);
 
-      let output_size = resampler
-        .as_mut()
-        .unwrap()
-        .process_all_needed_output_len(samples.len());
-      let mut resample_buffer = vec![0.; output_size];
-      let input_adapter = InterleavedSlice::new(&samples, 1, samples.len())?;
-      let mut output_adapter = InterleavedSlice::new_mut(&mut resample_buffer, 1, output_size)?;
-      resampler.as_mut().unwrap().process_all_into_buffer(
-        &input_adapter,
-        &mut output_adapter,
-        samples.len(),
-        None,
-      )?;
-
-      samples.resize(resample_buffer.len(), 0.);
-      samples.copy_from_slice(&resample_buffer);
-    }
+impl<S: Read> Read for SeekableSource<S> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    self.0.read(buf)
+  }
+}
 
-    // Calculate file duration (capped at the max buffer size) and apply fades
-    let duration_in_samples = if samples.len() > self.max_size {
-      self.fade_in_out.process(&mut samples[..self.max_size]);
-      self.max_size
-    } else {
-      self.fade_in_out.process(&mut samples);
-      samples.len()
-    };
-    let duration_in_ms = (duration_in_samples as f32).sampstoms(self.host_sample_rate as f32);
-    samples.resize(self.max_size, 0.); // Pad the buffer so it's full
+impl<S: Seek> Seek for SeekableSource<S> {
+  fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+    self.0.seek(pos)
+  }
+}
 
-    return Ok(AudioFileData {
-      samples,
-      duration_in_samples,
-      duration_in_ms,
-    });
+impl<S: Read + Seek + Send + Sync> MediaSource for SeekableSource<S> {
+  fn is_seekable(&self) -> bool {
+    true
+  }
+
+  fn byte_len(&self) -> Option<u64> {
+    None
   }
 }
 
@@ -238,7 +500,10 @@ impl AudioFileProcessor {
 mod tests {
   use crate::{
     assert_approximately_eq,
-    audio_file_processor::{fade_in_out::FadeInOut, AudioFileProcessingError, AudioFileProcessor},
+    audio_file_processor::{
+      fade_in_out::FadeInOut, AudioFile, AudioFileProcessingError, AudioFileProcessor,
+      ResamplerQuality, SampleProcessing,
+    },
     FADE_TIME,
   };
   use std::path::Path;
@@ -246,7 +511,13 @@ mod tests {
   #[test]
   fn should_throw_error_for_short_audio_file() {
     let file_path = Path::new("src/audio_file_processor/short_file.wav");
-    let mut audio_file_processor = AudioFileProcessor::new(44100., 44100);
+    let mut audio_file_processor = AudioFileProcessor::new(
+      44100.,
+      44100,
+      ResamplerQuality::Fft,
+      None,
+      SampleProcessing::default(),
+    );
     let result = audio_file_processor.read(file_path);
 
     assert!(result.is_err());
@@ -256,12 +527,40 @@ mod tests {
     };
   }
 
+  #[test]
+  fn should_throw_error_when_trimming_leaves_too_little() {
+    let file_path = Path::new("src/audio_file_processor/valid_mono_file.wav");
+    let sample_processing = SampleProcessing {
+      trim_threshold: Some(0.),
+      ..Default::default()
+    };
+    let mut audio_file_processor = AudioFileProcessor::new(
+      44100.,
+      44100,
+      ResamplerQuality::Fft,
+      None,
+      sample_processing,
+    );
+    let result = audio_file_processor.read(file_path);
+
+    assert!(matches!(
+      result,
+      Err(AudioFileProcessingError::DurationTooShortError)
+    ));
+  }
+
   #[test]
   fn should_read_mono_audio_file_correctly() {
     let sample_rate = 44100.;
     let file_path = Path::new("src/audio_file_processor/valid_mono_file.wav");
     let mut fade = FadeInOut::new(sample_rate, FADE_TIME as f32);
-    let mut audio_file_processor = AudioFileProcessor::new(sample_rate, 44100);
+    let mut audio_file_processor = AudioFileProcessor::new(
+      sample_rate,
+      44100,
+      ResamplerQuality::Fft,
+      None,
+      SampleProcessing::default(),
+    );
     let result = audio_file_processor.read(file_path);
 
     assert!(result.is_ok());
@@ -269,7 +568,8 @@ mod tests {
     match result {
       Ok(r) => {
         let mut expected = vec![0.5; 44100];
-        fade.process(&mut expected);
+        fade.fade_in(&mut expected);
+        fade.fade_out(&mut expected);
 
         r.samples
           .iter()
@@ -287,14 +587,21 @@ mod tests {
     let sample_rate = 44100.;
     let file_path = Path::new("src/audio_file_processor/valid_stereo_file.wav");
     let mut fade = FadeInOut::new(sample_rate, FADE_TIME as f32);
-    let mut audio_file_processor = AudioFileProcessor::new(sample_rate, 44100);
+    let mut audio_file_processor = AudioFileProcessor::new(
+      sample_rate,
+      44100,
+      ResamplerQuality::Fft,
+      None,
+      SampleProcessing::default(),
+    );
     let result = audio_file_processor.read(file_path);
 
     assert!(result.is_ok());
     match result {
       Ok(r) => {
         let mut expected = vec![0.5; 44100];
-        fade.process(&mut expected);
+        fade.fade_in(&mut expected);
+        fade.fade_out(&mut expected);
 
         r.samples
           .iter()
@@ -307,20 +614,94 @@ mod tests {
     }
   }
 
+  #[test]
+  fn should_read_from_bytes_sources_and_samples_like_from_a_path() {
+    let sample_rate = 44100.;
+    let file_path = Path::new("src/audio_file_processor/valid_mono_file.wav");
+    let mut audio_file_processor = AudioFileProcessor::new(
+      sample_rate,
+      44100,
+      ResamplerQuality::Fft,
+      None,
+      SampleProcessing::default(),
+    );
+    let expected = audio_file_processor.read(file_path).unwrap().samples;
+
+    let from_bytes = audio_file_processor
+      .read_from_bytes(std::fs::read(file_path).unwrap())
+      .unwrap();
+    let from_source = audio_file_processor
+      .read_from_source(std::fs::File::open(file_path).unwrap())
+      .unwrap();
+    let from_samples = audio_file_processor
+      .read_from_samples(vec![0.5; 44100], 44100)
+      .unwrap();
+
+    assert_eq!(from_bytes.samples, expected);
+    assert_eq!(from_source.samples, expected);
+    assert_eq!(from_samples.duration_in_samples, 44100);
+    from_samples
+      .samples
+      .iter()
+      .zip(expected.iter())
+      .for_each(|(actual, expected)| {
+        assert_approximately_eq!(*actual, expected, 4);
+      });
+  }
+
+  #[test]
+  fn should_open_an_embedded_sample_without_processing_it_again() {
+    let sample_processing = SampleProcessing {
+      reverse: true,
+      ..Default::default()
+    };
+    let mut audio_file_processor = AudioFileProcessor::new(
+      44100.,
+      44100,
+      ResamplerQuality::Fft,
+      None,
+      sample_processing,
+    );
+    let ramp = (0..22050).map(|i| i as f32 / 22050.).collect();
+    let expected = audio_file_processor.read_from_samples(ramp, 44100).unwrap();
+
+    let bytes = expected.encode().unwrap();
+    let actual = match audio_file_processor.open_embedded_sample(bytes) {
+      Ok(AudioFile::Buffered(data)) => data,
+      _ => panic!("the embedded sample should be buffered"),
+    };
+
+    assert_eq!(actual.duration_in_samples, expected.duration_in_samples);
+    actual
+      .samples
+      .iter()
+      .zip(expected.samples.iter())
+      .for_each(|(actual, expected)| {
+        assert_approximately_eq!(*actual, expected, 5);
+      });
+  }
+
   #[test]
   fn should_apply_fades_at_max_size_when_file_is_too_big() {
     let sample_rate = 44100.;
     // This file has a length of 44100 samples, but it will capped at 22050 samples
     let file_path = Path::new("src/audio_file_processor/valid_mono_file.wav");
     let mut fade = FadeInOut::new(sample_rate, FADE_TIME as f32);
-    let mut audio_file_processor = AudioFileProcessor::new(sample_rate, 22050);
+    let mut audio_file_processor = AudioFileProcessor::new(
+      sample_rate,
+      22050,
+      ResamplerQuality::Fft,
+      None,
+      SampleProcessing::default(),
+    );
     let result = audio_file_processor.read(file_path);
 
     assert!(result.is_ok());
     match result {
       Ok(r) => {
         let mut expected = vec![0.5; 22050];
-        fade.process(&mut expected);
+        fade.fade_in(&mut expected);
+        fade.fade_out(&mut expected);
 
         r.samples
           .iter()
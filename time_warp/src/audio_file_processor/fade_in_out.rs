@@ -16,14 +16,30 @@ impl FadeInOut {
     }
   }
 
-  pub fn process(&mut self, samples: &mut [f32]) {
-    for i in 0..self.fade_time_in_samples {
-      let fade = ((i as f32) * self.step_size).cubic_spline_curve();
+  pub fn fade_in(&mut self, samples: &mut [f32]) {
+    for (i, sample) in samples
+      .iter_mut()
+      .take(self.fade_time_in_samples)
+      .enumerate()
+    {
+      *sample *= self.get_fade(i);
+    }
+  }
 
-      samples[i] *= fade;
-      samples[samples.len() - i - 1] *= fade;
+  pub fn fade_out(&mut self, samples: &mut [f32]) {
+    for (i, sample) in samples
+      .iter_mut()
+      .rev()
+      .take(self.fade_time_in_samples)
+      .enumerate()
+    {
+      *sample *= self.get_fade(i);
     }
   }
+
+  fn get_fade(&self, index: usize) -> f32 {
+    ((index as f32) * self.step_size).cubic_spline_curve()
+  }
 }
 
 #[cfg(test)]
@@ -37,7 +53,8 @@ mod tests {
     let mut fade = FadeInOut::new(sample_rate, 10.);
     let mut samples = vec![1.; 1000];
 
-    fade.process(&mut samples);
+    fade.fade_in(&mut samples);
+    fade.fade_out(&mut samples);
 
     for i in 0..10 {
       assert_approximately_eq!(samples[i], (i as f32 * 0.1).cubic_spline_curve(), 7);
@@ -0,0 +1,377 @@
+use {
+  super::{
+    sample_processing::{LevelStats, Normalization, StreamProcessing},
+    AudioFileData, AudioFileProcessingError, AudioFileProcessor, SampleRateConversion, SourceFile,
+  },
+  crate::{delay_line::DelayLine, shared::float_ext::FloatExt, TimeWarp, MIN_DELAY_TIME},
+};
+
+const BLOCK_SIZE: usize = 65536;
+
+pub struct StreamWindow {
+  pub delay_line: DelayLine,
+  pub duration_in_ms: f32,
+  pub start_in_ms: f32,
+  pub file_duration_in_ms: f32,
+}
+
+/// Reads windows of a file that doesn't fit in the delay line. Decoded blocks of the file are
+/// cached, so moving the window only decodes the part of the file that wasn't read recently.
+pub struct FileStream {
+  source_file: SourceFile,
+  audio_file_processor: AudioFileProcessor,
+  processing: StreamProcessing,
+  // least recently used first
+  blocks: Vec<(usize, Vec<f32>)>,
+  max_cached_blocks: usize,
+}
+
+impl FileStream {
+  /// Decodes the whole file once when the sample processing needs the levels of the file.
+  pub(super) fn new(
+    source_file: SourceFile,
+    audio_file_processor: AudioFileProcessor,
+  ) -> Result<Self, AudioFileProcessingError> {
+    let processing = StreamProcessing::new(
+      source_file.duration,
+      audio_file_processor.sample_processing.reverse,
+    );
+    let mut file_stream = Self {
+      source_file,
+      audio_file_processor,
+      processing,
+      blocks: Vec::new(),
+      max_cached_blocks: 0,
+    };
+    file_stream.processing = file_stream.get_processing()?;
+    // trimming silence can leave too little of the file
+    if (file_stream.processing.get_length() as f32)
+      .sampstoms(file_stream.source_file.sample_rate as f32)
+      < MIN_DELAY_TIME
+    {
+      return Err(AudioFileProcessingError::DurationTooShortError);
+    }
+    // a whole window and the blocks on either side of it stay cached, so a window that moved only
+    // decodes the blocks it didn't overlap with before
+    file_stream.max_cached_blocks = file_stream.get_window_length().div_ceil(BLOCK_SIZE) + 2;
+    file_stream.blocks = Vec::with_capacity(file_stream.max_cached_blocks);
+    Ok(file_stream)
+  }
+
+  /// Returns the duration of the file after trimming.
+  pub fn get_duration_in_ms(&self) -> f32 {
+    (self.processing.get_length() as f32).sampstoms(self.source_file.sample_rate as f32)
+  }
+
+  pub fn get_sample_rate_conversion(&self) -> SampleRateConversion {
+    self.audio_file_processor.get_sample_rate_conversion(
+      self.source_file.sample_rate,
+      self.source_file.is_sample_rate_assumed,
+    )
+  }
+
+  /// Reads the part of the file that fills the delay line around `position`, which is relative to
+  /// the file duration. The delay line is built here, because rebuilding its mip map is too much
+  /// work for the audio thread.
+  pub fn read_window(&mut self, position: f32) -> Result<StreamWindow, AudioFileProcessingError> {
+    let sample_rate = self.source_file.sample_rate;
+    let length = self.processing.get_length();
+    let window_length = self.get_window_length();
+    let center = (position.clamp(0., 1.) as f64 * length as f64) as usize;
+    let start = center
+      .saturating_sub(window_length / 2)
+      .min(length - window_length);
+    let end = start + window_length;
+    // the window runs backwards through the file when the sample is reversed
+    let (file_start, file_end) = if self.processing.reverse {
+      (self.processing.end - end, self.processing.end - start)
+    } else {
+      (self.processing.start + start, self.processing.start + end)
+    };
+
+    let mut samples = Vec::with_capacity(window_length);
+    for index in file_start / BLOCK_SIZE..=(file_end - 1) / BLOCK_SIZE {
+      let block_start = index * BLOCK_SIZE;
+      let block = self.get_block(index)?;
+      let from = (file_start.max(block_start) - block_start).min(block.len());
+      let to = (file_end.min(block_start + BLOCK_SIZE) - block_start).min(block.len());
+      samples.extend_from_slice(&block[from..to]);
+    }
+    self.processing.process(&mut samples);
+    // pad the window when the file turns out to be shorter than its reported duration
+    samples.resize(window_length, 0.);
+
+    let samples = self.audio_file_processor.resample(samples, sample_rate)?;
+    let sample_rate_conversion = self.get_sample_rate_conversion();
+    let AudioFileData {
+      samples,
+      duration_in_samples,
+      duration_in_ms,
+      ..
+    } = self.audio_file_processor.fill_buffer(
+      samples,
+      sample_rate_conversion,
+      // the windows crossfade, so only the start and the end of the file fade
+      start == 0,
+      end == length,
+    );
+    Ok(StreamWindow {
+      delay_line: TimeWarp::create_delay_line_from_values(
+        samples,
+        duration_in_samples,
+        self.audio_file_processor.host_sample_rate as f32,
+      ),
+      duration_in_ms,
+      start_in_ms: (start as f32).sampstoms(sample_rate as f32),
+      file_duration_in_ms: self.get_duration_in_ms(),
+    })
+  }
+
+  /// Returns the length of a window in samples of the file, which fills the delay line once it's
+  /// resampled.
+  fn get_window_length(&self) -> usize {
+    ((self.audio_file_processor.max_size as f64 * self.source_file.sample_rate as f64
+      / self.audio_file_processor.host_sample_rate as f64) as usize)
+      .min(self.processing.get_length())
+  }
+
+  /// Works out the sample processing from the levels of the whole file. Only the blocks where
+  /// trimming ends are decoded again.
+  fn get_processing(&mut self) -> Result<StreamProcessing, AudioFileProcessingError> {
+    let sample_processing = self.audio_file_processor.sample_processing;
+    let mut processing = self.processing;
+    if !sample_processing.remove_dc
+      && sample_processing.trim_threshold.is_none()
+      && sample_processing.normalization == Normalization::Off
+    {
+      return Ok(processing);
+    }
+
+    let block_stats = (0..self.source_file.duration.div_ceil(BLOCK_SIZE))
+      .map(|index| Ok(LevelStats::new(&self.decode_block(index)?)))
+      .collect::<Result<Vec<_>, AudioFileProcessingError>>()?;
+    let file_stats = block_stats
+      .iter()
+      .fold(LevelStats::new(&[]), |stats, block| stats.add(block));
+    if sample_processing.remove_dc {
+      processing.offset = file_stats.get_mean();
+    }
+    let offset = processing.offset;
+
+    if let Some(threshold) = sample_processing.trim_threshold {
+      let threshold = threshold.dbtoa();
+      let is_audible = |x: &f32| (*x - offset).abs() >= threshold;
+      let first = block_stats
+        .iter()
+        .position(|stats| stats.get_peak(offset) >= threshold)
+        .ok_or(AudioFileProcessingError::DurationTooShortError)?;
+      let last = block_stats
+        .iter()
+        .rposition(|stats| stats.get_peak(offset) >= threshold)
+        .unwrap();
+      processing.start = first * BLOCK_SIZE
+        + self
+          .decode_block(first)?
+          .iter()
+          .position(is_audible)
+          .unwrap();
+      processing.end = last * BLOCK_SIZE
+        + self
+          .decode_block(last)?
+          .iter()
+          .rposition(is_audible)
+          .unwrap()
+        + 1;
+    }
+
+    if sample_processing.normalization != Normalization::Off {
+      // the blocks where trimming ends only count with their remaining part
+      let mut stats = LevelStats::new(&[]);
+      let first = processing.start / BLOCK_SIZE;
+      let last = (processing.end - 1) / BLOCK_SIZE;
+      for (index, block_stats) in block_stats.iter().enumerate().take(last + 1).skip(first) {
+        let block_start = index * BLOCK_SIZE;
+        if processing.start <= block_start && block_start + block_stats.length <= processing.end {
+          stats = stats.add(block_stats);
+        } else {
+          let block = self.decode_block(index)?;
+          let from = (processing.start.max(block_start) - block_start).min(block.len());
+          let to = (processing.end - block_start).min(block.len());
+          stats = stats.add(&LevelStats::new(&block[from..to]));
+        }
+      }
+      processing.gain = sample_processing.get_gain(&stats, offset);
+    }
+
+    Ok(processing)
+  }
+
+  fn get_block(&mut self, index: usize) -> Result<&[f32], AudioFileProcessingError> {
+    match self.blocks.iter().position(|(i, _)| *i == index) {
+      Some(position) => {
+        let block = self.blocks.remove(position);
+        self.blocks.push(block);
+      }
+      None => {
+        let block = self.decode_block(index)?;
+        if self.blocks.len() == self.max_cached_blocks {
+          self.blocks.remove(0);
+        }
+        self.blocks.push((index, block));
+      }
+    }
+    Ok(&self.blocks[self.blocks.len() - 1].1)
+  }
+
+  fn decode_block(&mut self, index: usize) -> Result<Vec<f32>, AudioFileProcessingError> {
+    let block_start = index * BLOCK_SIZE;
+    let block_length = BLOCK_SIZE.min(self.source_file.duration - block_start);
+    self.source_file.seek(block_start)?;
+
+    let mut block = Vec::with_capacity(block_length);
+    let mut samples = Vec::new();
+    while block.len() < block_length {
+      samples.clear();
+      let packet_start = match self.source_file.decode_next_packet(&mut samples)? {
+        Some(packet_start) => packet_start,
+        None => break,
+      };
+      let position = (block_start + block.len()) as i64;
+      if packet_start > position {
+        block.resize((packet_start as usize - block_start).min(block_length), 0.);
+      }
+      // seeking ends up before the requested frame, so the start of the packet may be skipped
+      let skip = ((block_start + block.len()) as i64 - packet_start).max(0) as usize;
+      if skip < samples.len() {
+        let take = (samples.len() - skip).min(block_length - block.len());
+        block.extend_from_slice(&samples[skip..skip + take]);
+      }
+    }
+
+    Ok(block)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    assert_approximately_eq,
+    audio_file_processor::{
+      flac_encoder::encode, AudioFile, AudioFileProcessor, Normalization, ResamplerQuality,
+      SampleProcessing,
+    },
+    delay_line::Interpolation,
+  };
+
+  #[test]
+  fn should_stream_file_that_exceeds_max_size() {
+    // This file has a length of 44100 samples, so it's read in windows of 32768 samples
+    let mut audio_file_processor = AudioFileProcessor::new(
+      44100.,
+      32768,
+      ResamplerQuality::Fft,
+      None,
+      SampleProcessing::default(),
+    );
+    let mut file_stream =
+      match audio_file_processor.open("src/audio_file_processor/valid_mono_file.wav") {
+        Ok(AudioFile::Streamed(file_stream)) => file_stream,
+        _ => panic!("expected the file to be streamed"),
+      };
+    assert_approximately_eq!(file_stream.get_duration_in_ms(), 1000., 4);
+
+    let window = file_stream.read_window(0.5).unwrap();
+    assert_approximately_eq!(window.start_in_ms, 5666. / 44.1, 3);
+    assert_approximately_eq!(window.duration_in_ms, 32768. / 44.1, 3);
+    for time in [10., 200., 700.] {
+      assert_approximately_eq!(window.delay_line.read(time, Interpolation::Linear), 0.5, 4);
+    }
+    // a window inside the file doesn't fade at its edges
+    for time in [1., window.duration_in_ms - 1.] {
+      assert_approximately_eq!(window.delay_line.read(time, Interpolation::Linear), 0.5, 4);
+    }
+
+    let window = file_stream.read_window(1.).unwrap();
+    assert_approximately_eq!(window.start_in_ms, 11332. / 44.1, 3);
+  }
+
+  #[test]
+  fn should_stream_file_from_bytes() {
+    let bytes = std::fs::read("src/audio_file_processor/valid_mono_file.wav").unwrap();
+    let mut audio_file_processor = AudioFileProcessor::new(
+      44100.,
+      32768,
+      ResamplerQuality::Fft,
+      None,
+      SampleProcessing::default(),
+    );
+    let mut file_stream = match audio_file_processor.open_from_bytes(bytes) {
+      Ok(AudioFile::Streamed(file_stream)) => file_stream,
+      _ => panic!("expected the file to be streamed"),
+    };
+
+    let window = file_stream.read_window(1.).unwrap();
+    assert_approximately_eq!(window.start_in_ms, 11332. / 44.1, 3);
+    assert_approximately_eq!(window.delay_line.read(200., Interpolation::Linear), 0.5, 4);
+  }
+
+  #[test]
+  fn should_process_a_streamed_file_like_a_buffered_one() {
+    let sample_processing = SampleProcessing {
+      normalization: Normalization::Peak,
+      trim_threshold: Some(-40.),
+      remove_dc: false,
+      reverse: true,
+    };
+    // silence on either side of a ramp, which is trimmed off
+    let samples: Vec<f32> = std::iter::repeat_n(0., 4410)
+      .chain((0..44100).map(|i| 0.1 + i as f32 / 88200.))
+      .chain(std::iter::repeat_n(0., 4410))
+      .collect();
+    let expected = AudioFileProcessor::new(
+      44100.,
+      65536,
+      ResamplerQuality::Fft,
+      None,
+      sample_processing,
+    )
+    .read_from_samples(samples.clone(), 44100)
+    .unwrap();
+
+    let mut audio_file_processor = AudioFileProcessor::new(
+      44100.,
+      32768,
+      ResamplerQuality::Fft,
+      None,
+      sample_processing,
+    );
+    let mut file_stream = match audio_file_processor.open_from_bytes(encode(&samples, 44100)) {
+      Ok(AudioFile::Streamed(file_stream)) => file_stream,
+      _ => panic!("expected the file to be streamed"),
+    };
+    assert_approximately_eq!(file_stream.get_duration_in_ms(), 1000., 3);
+
+    let window = file_stream.read_window(0.).unwrap();
+    for time in [100., 300., 600.] {
+      let index = 32768 - (time * 44.1) as usize;
+      assert_approximately_eq!(
+        window.delay_line.read(time, Interpolation::Linear),
+        expected.samples[index],
+        3
+      );
+    }
+  }
+
+  #[test]
+  fn should_buffer_file_that_fits_in_max_size() {
+    let mut audio_file_processor = AudioFileProcessor::new(
+      44100.,
+      65536,
+      ResamplerQuality::Fft,
+      None,
+      SampleProcessing::default(),
+    );
+    let result = audio_file_processor.open("src/audio_file_processor/valid_mono_file.wav");
+    assert!(matches!(result, Ok(AudioFile::Buffered(_))));
+  }
+}
@@ -0,0 +1,259 @@
+//! A minimal FLAC encoder for mono samples, which is enough to embed a sample in the plugin state.
+//! Every block uses the fixed predictor with the smallest residual, which is Rice coded as a
+//! single partition.
+
+const BLOCK_SIZE: usize = 4096;
+const BITS_PER_SAMPLE: u32 = 24;
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_RICE_PARAMETER: u32 = 30;
+
+/// Encodes the samples as 24 bit FLAC. Samples outside of -1 to 1 are clipped.
+pub fn encode(samples: &[f32], sample_rate: usize) -> Vec<u8> {
+  let max_value = ((1 << (BITS_PER_SAMPLE - 1)) - 1) as f32;
+  let samples: Vec<i32> = samples
+    .iter()
+    .map(|x| (x.clamp(-1., 1.) * max_value).round() as i32)
+    .collect();
+
+  let mut writer = BitWriter::new();
+  writer.write_bytes(b"fLaC");
+  write_stream_info(&mut writer, samples.len(), sample_rate);
+  for (frame_number, block) in samples.chunks(BLOCK_SIZE).enumerate() {
+    write_frame(&mut writer, block, frame_number);
+  }
+  writer.into_bytes()
+}
+
+fn write_stream_info(writer: &mut BitWriter, length: usize, sample_rate: usize) {
+  let block_size = BLOCK_SIZE.min(length) as u64;
+  // the last metadata block, of type STREAMINFO
+  writer.write(1, 1);
+  writer.write(0, 7);
+  writer.write(34, 24);
+  writer.write(block_size, 16);
+  writer.write(block_size, 16);
+  // the frame sizes and the MD5 signature are left unknown
+  writer.write(0, 24);
+  writer.write(0, 24);
+  writer.write(sample_rate as u64, 20);
+  writer.write(0, 3);
+  writer.write((BITS_PER_SAMPLE - 1) as u64, 5);
+  writer.write(length as u64, 36);
+  writer.write_bytes(&[0; 16]);
+}
+
+fn write_frame(writer: &mut BitWriter, block: &[i32], frame_number: usize) {
+  let frame_start = writer.len();
+  // sync code with a fixed block size, a 16 bit block size at the end of the header, the sample
+  // rate of the STREAMINFO block, a mono channel and 24 bit samples
+  writer.write_bytes(&[0xff, 0xf8, 0x70, 0x0c]);
+  write_utf8_number(writer, frame_number as u64);
+  writer.write((block.len() - 1) as u64, 16);
+  let crc8 = get_crc8(writer.bytes_since(frame_start));
+  writer.write(crc8 as u64, 8);
+
+  write_fixed_subframe(writer, block);
+  writer.align();
+  let crc16 = get_crc16(writer.bytes_since(frame_start));
+  writer.write(crc16 as u64, 16);
+}
+
+fn write_fixed_subframe(writer: &mut BitWriter, block: &[i32]) {
+  let (order, residual) = (0..=MAX_FIXED_ORDER.min(block.len() - 1))
+    .map(|order| (order, get_fixed_residual(block, order)))
+    .min_by_key(|(_, residual)| residual.iter().map(|r| r.unsigned_abs()).sum::<u64>())
+    .unwrap();
+
+  writer.write(0, 1);
+  writer.write(0b001000 | order as u64, 6);
+  writer.write(0, 1);
+  for sample in &block[..order] {
+    writer.write_signed(*sample as i64, BITS_PER_SAMPLE);
+  }
+
+  let residual: Vec<u64> = residual.iter().map(|r| zigzag(*r)).collect();
+  let rice_parameter = get_rice_parameter(&residual);
+  // Rice coding with 5 bit parameters, in a single partition
+  writer.write(0b01, 2);
+  writer.write(0, 4);
+  writer.write(rice_parameter as u64, 5);
+  for value in residual {
+    writer.write_unary(value >> rice_parameter);
+    writer.write(value & ((1 << rice_parameter) - 1), rice_parameter);
+  }
+}
+
+fn get_fixed_residual(block: &[i32], order: usize) -> Vec<i64> {
+  (order..block.len())
+    .map(|i| {
+      let x = |delay: usize| block[i - delay] as i64;
+      match order {
+        0 => x(0),
+        1 => x(0) - x(1),
+        2 => x(0) - 2 * x(1) + x(2),
+        3 => x(0) - 3 * x(1) + 3 * x(2) - x(3),
+        _ => x(0) - 4 * x(1) + 6 * x(2) - 4 * x(3) + x(4),
+      }
+    })
+    .collect()
+}
+
+/// Picks the parameter that's close to the mean of the values, which is close to optimal.
+fn get_rice_parameter(values: &[u64]) -> u32 {
+  if values.is_empty() {
+    return 0;
+  }
+  let mean = values.iter().sum::<u64>() / values.len() as u64;
+  (u64::BITS - mean.leading_zeros())
+    .saturating_sub(1)
+    .min(MAX_RICE_PARAMETER)
+}
+
+fn zigzag(value: i64) -> u64 {
+  if value >= 0 {
+    (value as u64) << 1
+  } else {
+    ((-value as u64) << 1) - 1
+  }
+}
+
+fn write_utf8_number(writer: &mut BitWriter, number: u64) {
+  if number < 0x80 {
+    writer.write(number, 8);
+    return;
+  }
+  let continuation_bytes = (1..6)
+    .find(|&count| number < 1 << (5 * count + 6))
+    .unwrap_or(6);
+  let leading_ones = (0xff00 >> (continuation_bytes + 1)) & 0xff;
+  writer.write(leading_ones | (number >> (6 * continuation_bytes)), 8);
+  for i in (0..continuation_bytes).rev() {
+    writer.write(0x80 | ((number >> (6 * i)) & 0x3f), 8);
+  }
+}
+
+fn get_crc8(bytes: &[u8]) -> u8 {
+  bytes.iter().fold(0, |crc, byte| {
+    (0..8).fold(crc ^ byte, |crc, _| {
+      if crc & 0x80 == 0 {
+        crc << 1
+      } else {
+        (crc << 1) ^ 0x07
+      }
+    })
+  })
+}
+
+fn get_crc16(bytes: &[u8]) -> u16 {
+  bytes.iter().fold(0, |crc, byte| {
+    (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+      if crc & 0x8000 == 0 {
+        crc << 1
+      } else {
+        (crc << 1) ^ 0x8005
+      }
+    })
+  })
+}
+
+struct BitWriter {
+  bytes: Vec<u8>,
+  buffer: u64,
+  bit_count: u32,
+}
+
+impl BitWriter {
+  fn new() -> Self {
+    Self {
+      bytes: Vec::new(),
+      buffer: 0,
+      bit_count: 0,
+    }
+  }
+
+  /// Writes the lowest `bits` bits of `value`, where `bits` is at most 32.
+  fn write(&mut self, value: u64, bits: u32) {
+    if bits == 0 {
+      return;
+    }
+    self.buffer = (self.buffer << bits) | (value & ((1 << bits) - 1));
+    self.bit_count += bits;
+    while self.bit_count >= 8 {
+      self.bit_count -= 8;
+      self.bytes.push((self.buffer >> self.bit_count) as u8);
+    }
+  }
+
+  fn write_signed(&mut self, value: i64, bits: u32) {
+    self.write(value as u64, bits);
+  }
+
+  fn write_unary(&mut self, value: u64) {
+    let mut zeros = value;
+    while zeros >= 32 {
+      self.write(0, 32);
+      zeros -= 32;
+    }
+    self.write(1, zeros as u32 + 1);
+  }
+
+  fn write_bytes(&mut self, bytes: &[u8]) {
+    bytes.iter().for_each(|byte| self.write(*byte as u64, 8));
+  }
+
+  /// Pads the last byte with zeros.
+  fn align(&mut self) {
+    if self.bit_count > 0 {
+      self.write(0, 8 - self.bit_count);
+    }
+  }
+
+  fn len(&self) -> usize {
+    self.bytes.len()
+  }
+
+  fn bytes_since(&self, start: usize) -> &[u8] {
+    &self.bytes[start..]
+  }
+
+  fn into_bytes(mut self) -> Vec<u8> {
+    self.align();
+    self.bytes
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::encode,
+    crate::audio_file_processor::SourceFile,
+    std::{f32::consts::TAU, io::Cursor},
+  };
+
+  #[test]
+  fn should_decode_the_encoded_samples() {
+    // long enough for frame numbers that take more than one byte
+    let samples: Vec<f32> = (0..600000)
+      .map(|i| (i as f32 * TAU * 0.01).sin() * 0.5 + (i % 7) as f32 * 0.01 - 0.03)
+      .chain([1., -1., 0.999, -0.999])
+      .collect();
+    let bytes = encode(&samples, 44100);
+
+    let mut source_file = SourceFile::new(Box::new(Cursor::new(bytes)), None).unwrap();
+    assert_eq!(source_file.sample_rate, 44100);
+    assert_eq!(source_file.duration, samples.len());
+    let mut decoded_samples = Vec::new();
+    while source_file
+      .decode_next_packet(&mut decoded_samples)
+      .unwrap()
+      .is_some()
+    {}
+    assert_eq!(decoded_samples.len(), samples.len());
+    decoded_samples
+      .iter()
+      .zip(samples)
+      .for_each(|(actual, expected)| {
+        assert!((actual - expected).abs() < 1e-6);
+      });
+  }
+}
@@ -0,0 +1,147 @@
+use {
+  super::AudioFileProcessingError,
+  rubato::{
+    audioadapter_buffers::direct::InterleavedSlice, calculate_cutoff, Async, Fft, FixedAsync,
+    FixedSync, PolynomialDegree, Resampler, SincInterpolationParameters, SincInterpolationType,
+    WindowFunction,
+  },
+  std::fmt,
+};
+
+const CHUNK_SIZE: usize = 1024;
+
+/// The resampler that converts files to the host sample rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResamplerQuality {
+  /// Cubic polynomial interpolation, which is quick but lets some aliasing through.
+  Fast,
+  /// Windowed sinc interpolation with a filter of the given length, where longer is cleaner.
+  Sinc(usize),
+  /// FFT based resampling, which is clean for common sample rate ratios.
+  Fft,
+}
+
+impl fmt::Display for ResamplerQuality {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ResamplerQuality::Fast => write!(f, "polynomial"),
+      ResamplerQuality::Sinc(sinc_len) => write!(f, "sinc, {} taps", sinc_len),
+      ResamplerQuality::Fft => write!(f, "FFT"),
+    }
+  }
+}
+
+/// How a file was converted to the host sample rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampleRateConversion {
+  pub file_sample_rate: usize,
+  pub host_sample_rate: usize,
+  /// `None` when the sample rates match, so the file isn't resampled.
+  pub resampler_quality: Option<ResamplerQuality>,
+  /// Whether the file has no sample rate, so the fallback sample rate is used.
+  pub is_sample_rate_assumed: bool,
+}
+
+impl fmt::Display for SampleRateConversion {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} Hz", self.file_sample_rate)?;
+    if self.is_sample_rate_assumed {
+      write!(f, " (assumed)")?;
+    }
+    match self.resampler_quality {
+      Some(quality) => write!(f, " to {} Hz ({})", self.host_sample_rate, quality),
+      None => write!(f, ", not resampled"),
+    }
+  }
+}
+
+pub fn resample(
+  samples: &[f32],
+  file_sample_rate: usize,
+  host_sample_rate: usize,
+  quality: ResamplerQuality,
+) -> Result<Vec<f32>, AudioFileProcessingError> {
+  let ratio = host_sample_rate as f64 / file_sample_rate as f64;
+  match quality {
+    ResamplerQuality::Fast => process_all(
+      Async::<f32>::new_poly(
+        ratio,
+        1.,
+        PolynomialDegree::Cubic,
+        CHUNK_SIZE,
+        1,
+        FixedAsync::Input,
+      )?,
+      samples,
+    ),
+    ResamplerQuality::Sinc(sinc_len) => {
+      let window = WindowFunction::BlackmanHarris2;
+      let parameters = SincInterpolationParameters {
+        sinc_len,
+        f_cutoff: calculate_cutoff(sinc_len, window),
+        oversampling_factor: 256,
+        interpolation: SincInterpolationType::Cubic,
+        window,
+      };
+      process_all(
+        Async::<f32>::new_sinc(ratio, 1., &parameters, CHUNK_SIZE, 1, FixedAsync::Input)?,
+        samples,
+      )
+    }
+    ResamplerQuality::Fft => process_all(
+      Fft::<f32>::new(
+        file_sample_rate,
+        host_sample_rate,
+        CHUNK_SIZE,
+        2, // number of FFT blocks per processing call
+        1,
+        FixedSync::Both,
+      )?,
+      samples,
+    ),
+  }
+}
+
+fn process_all(
+  mut resampler: impl Resampler<f32>,
+  samples: &[f32],
+) -> Result<Vec<f32>, AudioFileProcessingError> {
+  let output_size = resampler.process_all_needed_output_len(samples.len());
+  let mut resample_buffer = vec![0.; output_size];
+  let input_adapter = InterleavedSlice::new(samples, 1, samples.len())?;
+  let mut output_adapter = InterleavedSlice::new_mut(&mut resample_buffer, 1, output_size)?;
+  let (_, output_len) =
+    resampler.process_all_into_buffer(&input_adapter, &mut output_adapter, samples.len(), None)?;
+  resample_buffer.truncate(output_len);
+
+  Ok(resample_buffer)
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::{resample, ResamplerQuality},
+    crate::assert_approximately_eq,
+    std::f32::consts::TAU,
+  };
+
+  #[test]
+  fn should_resample_with_each_quality() {
+    let input: Vec<f32> = (0..44100)
+      .map(|i| (i as f32 * 100. / 44100. * TAU).sin())
+      .collect();
+
+    for quality in [
+      ResamplerQuality::Fast,
+      ResamplerQuality::Sinc(64),
+      ResamplerQuality::Sinc(256),
+      ResamplerQuality::Fft,
+    ] {
+      let output = resample(&input, 44100, 48000, quality).unwrap();
+      assert!(output.len().abs_diff(48000) <= 1);
+      for i in [12000, 24000, 36000] {
+        assert_approximately_eq!(output[i], (i as f32 * 100. / 48000. * TAU).sin(), 1);
+      }
+    }
+  }
+}
@@ -0,0 +1,242 @@
+use crate::shared::float_ext::FloatExt;
+
+const PEAK_TARGET: f32 = -1.;
+const LOUDNESS_TARGET: f32 = -18.;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalization {
+  Off,
+  /// Scales the sample so its peak is at -1 dB.
+  Peak,
+  /// Scales the sample so its RMS level is at -18 dB, as far as the peak stays below -1 dB.
+  Loudness,
+}
+
+/// Processing that's applied to a file after it's decoded and resampled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampleProcessing {
+  pub normalization: Normalization,
+  /// Leading and trailing samples below this level in dB are cut off, `None` keeps them.
+  pub trim_threshold: Option<f32>,
+  pub remove_dc: bool,
+  pub reverse: bool,
+}
+
+impl Default for SampleProcessing {
+  fn default() -> Self {
+    Self {
+      normalization: Normalization::Off,
+      trim_threshold: None,
+      remove_dc: false,
+      reverse: false,
+    }
+  }
+}
+
+/// The processing of a streamed file, which is worked out from the whole file when the stream is
+/// opened, because a window of the file isn't enough to trim or normalize it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct StreamProcessing {
+  /// The part of the file that's left after trimming.
+  pub start: usize,
+  pub end: usize,
+  /// The DC offset that's removed.
+  pub offset: f32,
+  pub gain: f32,
+  pub reverse: bool,
+}
+
+impl StreamProcessing {
+  pub fn new(duration: usize, reverse: bool) -> Self {
+    Self {
+      start: 0,
+      end: duration,
+      offset: 0.,
+      gain: 1.,
+      reverse,
+    }
+  }
+
+  pub fn get_length(&self) -> usize {
+    self.end - self.start
+  }
+
+  /// Processes a window of the file, which is read backwards when the sample is reversed.
+  pub fn process(&self, samples: &mut [f32]) {
+    if self.reverse {
+      samples.reverse();
+    }
+    samples
+      .iter_mut()
+      .for_each(|x| *x = (*x - self.offset) * self.gain);
+  }
+}
+
+/// The level of a part of a file, which adds up to the level of the whole file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct LevelStats {
+  min: f32,
+  max: f32,
+  sum: f64,
+  sum_of_squares: f64,
+  pub length: usize,
+}
+
+impl LevelStats {
+  pub fn new(samples: &[f32]) -> Self {
+    samples.iter().fold(
+      Self {
+        min: f32::INFINITY,
+        max: f32::NEG_INFINITY,
+        sum: 0.,
+        sum_of_squares: 0.,
+        length: 0,
+      },
+      |stats, x| Self {
+        min: stats.min.min(*x),
+        max: stats.max.max(*x),
+        sum: stats.sum + *x as f64,
+        sum_of_squares: stats.sum_of_squares + (*x as f64).powi(2),
+        length: stats.length + 1,
+      },
+    )
+  }
+
+  pub fn add(&self, other: &Self) -> Self {
+    Self {
+      min: self.min.min(other.min),
+      max: self.max.max(other.max),
+      sum: self.sum + other.sum,
+      sum_of_squares: self.sum_of_squares + other.sum_of_squares,
+      length: self.length + other.length,
+    }
+  }
+
+  pub fn get_mean(&self) -> f32 {
+    if self.length == 0 {
+      return 0.;
+    }
+    (self.sum / self.length as f64) as f32
+  }
+
+  /// Returns the peak once `offset` is subtracted.
+  pub fn get_peak(&self, offset: f32) -> f32 {
+    (self.max - offset).max(offset - self.min).max(0.)
+  }
+
+  /// Returns the RMS level once `offset` is subtracted.
+  pub fn get_rms(&self, offset: f32) -> f32 {
+    if self.length == 0 {
+      return 0.;
+    }
+    let offset = offset as f64;
+    let sum_of_squares =
+      self.sum_of_squares - 2. * offset * self.sum + self.length as f64 * offset.powi(2);
+    (sum_of_squares.max(0.) / self.length as f64).sqrt() as f32
+  }
+}
+
+impl SampleProcessing {
+  pub fn process(&self, samples: &mut Vec<f32>) {
+    if self.remove_dc {
+      Self::remove_dc(samples);
+    }
+    if let Some(threshold) = self.trim_threshold {
+      Self::trim_silence(samples, threshold.dbtoa());
+    }
+    if self.reverse {
+      samples.reverse();
+    }
+    if self.normalization != Normalization::Off {
+      let gain = self.get_gain(&LevelStats::new(samples), 0.);
+      samples.iter_mut().for_each(|x| *x *= gain);
+    }
+  }
+
+  /// Returns the gain that normalizes a sample with these levels, once `offset` is subtracted.
+  pub(super) fn get_gain(&self, stats: &LevelStats, offset: f32) -> f32 {
+    let peak = stats.get_peak(offset);
+    if peak == 0. {
+      return 1.;
+    }
+    let target_peak = match self.normalization {
+      Normalization::Off => return 1.,
+      Normalization::Peak => PEAK_TARGET.dbtoa(),
+      Normalization::Loudness => {
+        let rms = stats.get_rms(offset);
+        if rms == 0. {
+          return 1.;
+        }
+        peak * LOUDNESS_TARGET.dbtoa() / rms
+      }
+    };
+    // the target is capped at the peak target
+    target_peak.min(PEAK_TARGET.dbtoa()) / peak
+  }
+
+  fn remove_dc(samples: &mut [f32]) {
+    if samples.is_empty() {
+      return;
+    }
+    let mean = samples.iter().map(|x| *x as f64).sum::<f64>() / samples.len() as f64;
+    samples.iter_mut().for_each(|x| *x -= mean as f32);
+  }
+
+  fn trim_silence(samples: &mut Vec<f32>, threshold: f32) {
+    match samples.iter().position(|x| x.abs() >= threshold) {
+      Some(start) => {
+        let end = samples.iter().rposition(|x| x.abs() >= threshold).unwrap() + 1;
+        samples.truncate(end);
+        samples.drain(..start);
+      }
+      None => samples.clear(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::assert_approximately_eq;
+
+  #[test]
+  fn should_remove_dc_trim_and_reverse() {
+    let mut samples = vec![0.5, 0.5, 0.6, 0.4, 0.5, 0.5];
+    SampleProcessing {
+      normalization: Normalization::Off,
+      trim_threshold: Some(-40.),
+      remove_dc: true,
+      reverse: true,
+    }
+    .process(&mut samples);
+
+    let expected = [-0.1, 0.1];
+    assert_eq!(samples.len(), expected.len());
+    samples
+      .iter()
+      .zip(expected.iter())
+      .for_each(|(actual, expected)| {
+        assert_approximately_eq!(*actual, *expected, 4);
+      });
+  }
+
+  #[test]
+  fn should_normalize_peak_and_loudness() {
+    let mut peak_samples = vec![0.25, -0.5, 0.1];
+    let mut loudness_samples = vec![0.01, -0.01, 0.01, -0.01];
+    SampleProcessing {
+      normalization: Normalization::Peak,
+      ..Default::default()
+    }
+    .process(&mut peak_samples);
+    SampleProcessing {
+      normalization: Normalization::Loudness,
+      ..Default::default()
+    }
+    .process(&mut loudness_samples);
+
+    assert_approximately_eq!(peak_samples[1], -PEAK_TARGET.dbtoa(), 4);
+    assert_approximately_eq!(peak_samples[0], PEAK_TARGET.dbtoa() * 0.5, 4);
+    assert_approximately_eq!(loudness_samples[0], LOUDNESS_TARGET.dbtoa(), 4);
+  }
+}
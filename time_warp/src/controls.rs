@@ -0,0 +1,53 @@
+use crate::params::Params;
+
+/// The smoothed parameter values that only need control rate, so they're read once per block
+/// instead of at every sample.
+#[derive(Default)]
+pub struct Controls {
+  pub size: f32,
+  pub density: f32,
+  pub recycle: f32,
+  pub feedback: f32,
+  pub shimmer: f32,
+  pub drive: f32,
+  pub wow: f32,
+  pub flutter: f32,
+  pub filter_cutoff: f32,
+  pub filter_resonance: f32,
+  pub reverb_size: f32,
+  pub reverb_mix: f32,
+  pub eq_low: f32,
+  pub eq_mid: f32,
+  pub eq_high: f32,
+  pub attack: f32,
+  pub decay: f32,
+  pub sustain: f32,
+  pub release: f32,
+}
+
+impl Controls {
+  /// Reads the values for the next `samples` samples, which moves their smoothers ahead as far.
+  pub fn read(params: &mut Params, samples: usize) -> Self {
+    Self {
+      size: params.size,
+      density: params.density.advance(samples),
+      recycle: params.recycle.advance(samples),
+      feedback: params.feedback.advance(samples),
+      shimmer: params.shimmer.advance(samples),
+      drive: params.drive.advance(samples),
+      wow: params.wow.advance(samples),
+      flutter: params.flutter.advance(samples),
+      filter_cutoff: params.filter_cutoff.advance(samples),
+      filter_resonance: params.filter_resonance.advance(samples),
+      reverb_size: params.reverb_size.advance(samples),
+      reverb_mix: params.reverb_mix.advance(samples),
+      eq_low: params.eq_low.advance(samples),
+      eq_mid: params.eq_mid.advance(samples),
+      eq_high: params.eq_high.advance(samples),
+      attack: params.attack.advance(samples),
+      decay: params.decay.advance(samples),
+      sustain: params.sustain.advance(samples),
+      release: params.release.advance(samples),
+    }
+  }
+}
@@ -1,13 +1,22 @@
-use std::{f32::consts::PI, mem};
+mod mip_map;
+mod reader;
+pub use {mip_map::BandLimit, reader::DelayLineReader};
+use {
+  mip_map::MipMap,
+  std::{f32::consts::PI, mem, sync::OnceLock},
+};
 
-#[allow(dead_code)]
-#[derive(Clone, Copy)]
+const SINC_TAPS: usize = 8;
+const SINC_RESOLUTION: usize = 512;
+
+#[derive(Clone, Copy, PartialEq)]
 pub enum Interpolation {
   Step,
   Linear,
   Cosine,
   Cubic,
   Spline,
+  Sinc,
 }
 
 #[derive(Clone)]
@@ -16,22 +25,60 @@ pub struct DelayLine {
   write_pointer: usize,
   sample_rate: f32,
   wrap: usize,
+  mip_map: Option<MipMap>,
 }
 
 impl DelayLine {
   pub fn new(length: usize, sample_rate: f32) -> Self {
     let size = length.next_power_of_two();
+    // build the shared table up front, so the audio thread never allocates it
+    Self::get_sinc_table();
     Self {
       buffer: vec![0.0; size],
       write_pointer: 0,
       sample_rate,
       wrap: size - 1,
+      mip_map: None,
     }
   }
 
+  /// Creates a delay line that also keeps downsampled copies of its content, which
+  /// `read_band_limited` uses to read at speeds above 1 without aliasing.
+  pub fn new_band_limited(length: usize, sample_rate: f32) -> Self {
+    let mut delay_line = Self::new(length, sample_rate);
+    delay_line.mip_map = Some(MipMap::new(delay_line.buffer.len(), sample_rate));
+    delay_line
+  }
+
+  /// Creates a delay line that holds `values`, so it can be prepared off the audio thread. This
+  /// also builds the mip map when it's band limited. The length of `values` should be a power of
+  /// two.
+  pub fn from_values(
+    values: Vec<f32>,
+    write_pointer_index: usize,
+    sample_rate: f32,
+    is_band_limited: bool,
+  ) -> Self {
+    debug_assert!(values.len().is_power_of_two());
+    Self::get_sinc_table();
+    let size = values.len();
+    let mut delay_line = Self {
+      buffer: values,
+      write_pointer: write_pointer_index & (size - 1),
+      sample_rate,
+      wrap: size - 1,
+      mip_map: is_band_limited.then(|| MipMap::new(size, sample_rate)),
+    };
+    delay_line.rebuild_mip_map();
+    delay_line
+  }
+
   pub fn reset(&mut self) {
     self.buffer.fill(0.);
     self.write_pointer = 0;
+    if let Some(mip_map) = &mut self.mip_map {
+      mip_map.reset();
+    }
   }
 
   pub fn read(&self, time: f32, interp: Interpolation) -> f32 {
@@ -41,12 +88,32 @@ impl DelayLine {
       Interpolation::Cosine => self.cosine_interp(time),
       Interpolation::Cubic => self.cubic_interp(time),
       Interpolation::Spline => self.spline_interp(time),
+      Interpolation::Sinc => self.sinc_interp(time),
     }
   }
 
+  pub fn read_band_limited(&self, time: f32, interp: Interpolation, band_limit: BandLimit) -> f32 {
+    match &self.mip_map {
+      Some(mip_map) => mip_map.read(self, time, interp, band_limit),
+      None => self.read(time, interp),
+    }
+  }
+
+  pub fn get_band_limit(&self, speed: f64) -> BandLimit {
+    self
+      .mip_map
+      .as_ref()
+      .map_or(BandLimit::default(), |mip_map| {
+        mip_map.get_band_limit(speed)
+      })
+  }
+
   pub fn write(&mut self, value: f32) {
     self.buffer[self.write_pointer] = value;
     self.write_pointer = self.write_pointer + 1 & self.wrap;
+    if let Some(mip_map) = &mut self.mip_map {
+      mip_map.write(value);
+    }
   }
 
   pub fn set_values(&mut self, mut values: Vec<f32>) {
@@ -57,6 +124,16 @@ impl DelayLine {
     self.write_pointer = index & self.wrap;
   }
 
+  /// Recomputes the downsampled copies after the buffer was replaced, from the oldest sample on.
+  fn rebuild_mip_map(&mut self) {
+    if let Some(mip_map) = &mut self.mip_map {
+      mip_map.reset();
+      for i in 0..self.buffer.len() {
+        mip_map.write(self.buffer[(self.write_pointer + i) & self.wrap]);
+      }
+    }
+  }
+
   pub fn get_size(&self) -> usize {
     self.buffer.len()
   }
@@ -137,6 +214,51 @@ impl DelayLine {
     ((c3 * mix + c2) * mix + c1) * mix + c0
   }
 
+  fn sinc_interp(&self, time: f32) -> f32 {
+    let read_pointer = (self.write_pointer + self.buffer.len()) as f32
+      - self.mstosamps(time).max((SINC_TAPS / 2) as f32);
+    let rounded_read_pointer = read_pointer.trunc();
+    let mix = (read_pointer - rounded_read_pointer) * SINC_RESOLUTION as f32;
+    let rounded_mix = mix.trunc();
+    let table_mix = mix - rounded_mix;
+    let index = rounded_read_pointer as usize + self.buffer.len() + 1 - SINC_TAPS / 2;
+
+    let sinc_table = Self::get_sinc_table();
+    let row = rounded_mix as usize;
+    let (x, y) = (&sinc_table[row], &sinc_table[row + 1]);
+    (0..SINC_TAPS).fold(0., |result, tap| {
+      let weight = x[tap] + (y[tap] - x[tap]) * table_mix;
+      result + self.buffer[(index + tap) & self.wrap] * weight
+    })
+  }
+
+  /// Blackman-windowed sinc kernels for fractional offsets between 0 and 1, normalized to unity gain.
+  fn get_sinc_table() -> &'static [[f32; SINC_TAPS]] {
+    static SINC_TABLE: OnceLock<Vec<[f32; SINC_TAPS]>> = OnceLock::new();
+    SINC_TABLE.get_or_init(|| {
+      let half_taps = (SINC_TAPS / 2) as f64;
+      (0..=SINC_RESOLUTION)
+        .map(|row| {
+          let offset = row as f64 / SINC_RESOLUTION as f64;
+          let mut kernel = [0.; SINC_TAPS];
+          for (tap, weight) in kernel.iter_mut().enumerate() {
+            let x = tap as f64 + 1. - half_taps - offset;
+            let sinc = if x == 0. {
+              1.
+            } else {
+              (x * std::f64::consts::PI).sin() / (x * std::f64::consts::PI)
+            };
+            let phase = std::f64::consts::PI * x / half_taps;
+            let window = 0.42 + 0.5 * phase.cos() + 0.08 * (2. * phase).cos();
+            *weight = sinc * window;
+          }
+          let sum: f64 = kernel.iter().sum();
+          kernel.map(|weight| (weight / sum) as f32)
+        })
+        .collect()
+    })
+  }
+
   fn mstosamps(&self, time: f32) -> f32 {
     time * 0.001 * self.sample_rate
   }
@@ -144,7 +266,10 @@ impl DelayLine {
 
 #[cfg(test)]
 mod tests {
-  use super::DelayLine;
+  use {
+    super::{DelayLine, Interpolation},
+    crate::assert_approximately_eq,
+  };
 
   #[test]
   fn should_set_values() {
@@ -161,4 +286,56 @@ mod tests {
         assert_eq!(*actual, expected);
       });
   }
+
+  #[test]
+  fn should_rebuild_mip_map_from_values() {
+    let sample_rate = 1000.;
+    let mut delay_line = DelayLine::new_band_limited(256, sample_rate);
+    let values: Vec<f32> = (0..256).map(|i| (i as f32 * 0.05).sin()).collect();
+    for value in &values {
+      delay_line.write(*value);
+    }
+    let mut rebuilt_delay_line = DelayLine::new_band_limited(256, sample_rate);
+    rebuilt_delay_line.set_values(values);
+    rebuilt_delay_line.set_write_pointer(0);
+    rebuilt_delay_line.rebuild_mip_map();
+
+    let band_limit = delay_line.get_band_limit(3.);
+    for i in 0..50 {
+      let time = 40. + i as f32 * 2.1;
+      assert_eq!(
+        rebuilt_delay_line.read_band_limited(time, Interpolation::Linear, band_limit),
+        delay_line.read_band_limited(time, Interpolation::Linear, band_limit)
+      );
+    }
+  }
+
+  #[test]
+  fn sinc_interpolation_should_match_samples_at_whole_delays() {
+    let sample_rate = 1000.;
+    let mut delay_line = DelayLine::new(64, sample_rate);
+    for i in 0..64 {
+      delay_line.write((i as f32 * 0.3).sin());
+    }
+    for delay in 4..32 {
+      let expected = delay_line.read(delay as f32, Interpolation::Step);
+      let actual = delay_line.read(delay as f32, Interpolation::Sinc);
+      assert_approximately_eq!(actual, expected, 4);
+    }
+  }
+
+  #[test]
+  fn sinc_interpolation_should_be_close_to_linear_for_slow_signals() {
+    let sample_rate = 1000.;
+    let mut delay_line = DelayLine::new(256, sample_rate);
+    for i in 0..256 {
+      delay_line.write((i as f32 * 0.01).sin());
+    }
+    for i in 0..100 {
+      let time = 10. + i as f32 * 0.37;
+      let expected = delay_line.read(time, Interpolation::Linear);
+      let actual = delay_line.read(time, Interpolation::Sinc);
+      assert_approximately_eq!(actual, expected, 3);
+    }
+  }
 }
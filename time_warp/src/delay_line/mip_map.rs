@@ -0,0 +1,229 @@
+use super::{DelayLine, Interpolation};
+
+const LEVEL_COUNT: usize = 4;
+const TAPS: usize = 31;
+const HALF_TAPS: usize = TAPS / 2;
+
+/// Which mip map levels to read from for a given playback speed.
+#[derive(Clone, Copy, Default)]
+pub struct BandLimit {
+  level: usize,
+  mix: f32,
+}
+
+/// Copies of a delay line at half, a quarter, an eighth and a sixteenth of the sample rate, so
+/// reads at higher speeds can use a copy that has no content above their Nyquist frequency.
+#[derive(Clone)]
+pub struct MipMap {
+  levels: Vec<DelayLine>,
+  decimators: Vec<Decimator>,
+  offsets: Vec<f32>,
+  samples_since_write: Vec<usize>,
+  sample_factor: f32,
+}
+
+impl MipMap {
+  pub fn new(size: usize, sample_rate: f32) -> Self {
+    let coefficients = Self::get_coefficients();
+
+    Self {
+      levels: (1..=LEVEL_COUNT)
+        .map(|level| DelayLine::new(size >> level, sample_rate / (1 << level) as f32))
+        .collect(),
+      decimators: vec![Decimator::new(coefficients); LEVEL_COUNT],
+      // each level lags behind by the filter latency of all levels above it
+      offsets: (1..=LEVEL_COUNT)
+        .map(|level| {
+          let factor = 1 << level;
+          (HALF_TAPS * (factor - 1)) as f32 + 1. - factor as f32
+        })
+        .collect(),
+      samples_since_write: vec![0; LEVEL_COUNT],
+      sample_factor: 1000. / sample_rate,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.levels.iter_mut().for_each(|level| level.reset());
+    self
+      .decimators
+      .iter_mut()
+      .for_each(|decimator| decimator.reset());
+    self.samples_since_write.fill(0);
+  }
+
+  pub fn write(&mut self, value: f32) {
+    self
+      .samples_since_write
+      .iter_mut()
+      .for_each(|samples| *samples += 1);
+
+    let mut value = value;
+    for i in 0..LEVEL_COUNT {
+      match self.decimators[i].process(value) {
+        Some(decimated) => {
+          self.levels[i].write(decimated);
+          self.samples_since_write[i] = 0;
+          value = decimated;
+        }
+        None => break,
+      }
+    }
+  }
+
+  pub fn get_band_limit(&self, speed: f64) -> BandLimit {
+    let octave = (speed.abs().log2().max(0.) as f32).min(LEVEL_COUNT as f32);
+    let level = octave.trunc() as usize;
+    BandLimit {
+      level,
+      mix: octave - level as f32,
+    }
+  }
+
+  /// Crossfades between the two levels around the playback speed, so speed changes don't click.
+  pub fn read(
+    &self,
+    delay_line: &DelayLine,
+    time: f32,
+    interp: Interpolation,
+    band_limit: BandLimit,
+  ) -> f32 {
+    let BandLimit { level, mix } = band_limit;
+    let lower = self.read_level(delay_line, level, time, interp);
+    if mix == 0. {
+      lower
+    } else {
+      let upper = self.read_level(delay_line, level + 1, time, interp);
+      lower + (upper - lower) * mix
+    }
+  }
+
+  fn read_level(
+    &self,
+    delay_line: &DelayLine,
+    level: usize,
+    time: f32,
+    interp: Interpolation,
+  ) -> f32 {
+    if level == 0 {
+      return delay_line.read(time, interp);
+    }
+    let i = level - 1;
+    let offset = (self.offsets[i] + self.samples_since_write[i] as f32) * self.sample_factor;
+    self.levels[i].read(time - offset, interp)
+  }
+
+  /// A Blackman-windowed halfband lowpass, normalized to unity gain.
+  fn get_coefficients() -> [f32; TAPS] {
+    let mut coefficients = [0.; TAPS];
+    for (i, coefficient) in coefficients.iter_mut().enumerate() {
+      let x = i as f64 - HALF_TAPS as f64;
+      let sinc = if x == 0. {
+        1.
+      } else {
+        (x * 0.5 * std::f64::consts::PI).sin() / (x * 0.5 * std::f64::consts::PI)
+      };
+      let phase = std::f64::consts::PI * x / (HALF_TAPS + 1) as f64;
+      let window = 0.42 + 0.5 * phase.cos() + 0.08 * (2. * phase).cos();
+      *coefficient = (sinc * window) as f32;
+    }
+    let sum: f32 = coefficients.iter().sum();
+    coefficients.map(|coefficient| coefficient / sum)
+  }
+}
+
+/// Lowpass filters its input and returns every second sample.
+#[derive(Clone)]
+struct Decimator {
+  coefficients: [f32; TAPS],
+  history: [f32; TAPS],
+  index: usize,
+  should_output: bool,
+}
+
+impl Decimator {
+  fn new(coefficients: [f32; TAPS]) -> Self {
+    Self {
+      coefficients,
+      history: [0.; TAPS],
+      index: 0,
+      should_output: false,
+    }
+  }
+
+  fn reset(&mut self) {
+    self.history = [0.; TAPS];
+    self.index = 0;
+    self.should_output = false;
+  }
+
+  fn process(&mut self, input: f32) -> Option<f32> {
+    self.history[self.index] = input;
+    self.index = (self.index + 1) % TAPS;
+    self.should_output = !self.should_output;
+    if !self.should_output {
+      return None;
+    }
+
+    // the odd taps of a halfband filter are zero, except for the center tap
+    let output = (0..TAPS)
+      .filter(|tap| tap % 2 == 0 || *tap == HALF_TAPS)
+      .fold(0., |result, tap| {
+        result + self.history[(self.index + tap) % TAPS] * self.coefficients[tap]
+      });
+    Some(output)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::MipMap,
+    crate::{
+      assert_approximately_eq,
+      delay_line::{DelayLine, Interpolation},
+    },
+    std::f32::consts::TAU,
+  };
+
+  fn write_sine(delay_line: &mut DelayLine, mip_map: &mut MipMap, freq: f32, sample_rate: f32) {
+    for i in 0..8192 {
+      let value = (i as f32 * freq / sample_rate * TAU).sin();
+      delay_line.write(value);
+      mip_map.write(value);
+    }
+  }
+
+  #[test]
+  fn levels_should_be_aligned_with_the_delay_line() {
+    let sample_rate = 44100.;
+    let mut delay_line = DelayLine::new(8192, sample_rate);
+    let mut mip_map = MipMap::new(delay_line.get_size(), sample_rate);
+    write_sine(&mut delay_line, &mut mip_map, 100., sample_rate);
+
+    for speed in [2., 4., 8., 16.] {
+      let band_limit = mip_map.get_band_limit(speed);
+      for i in 0..20 {
+        let time = 20. + i as f32 * 3.3;
+        let expected = delay_line.read(time, Interpolation::Sinc);
+        let actual = mip_map.read(&delay_line, time, Interpolation::Sinc, band_limit);
+        assert_approximately_eq!(actual, expected, 3);
+      }
+    }
+  }
+
+  #[test]
+  fn levels_should_remove_content_above_their_nyquist_frequency() {
+    let sample_rate = 44100.;
+    let mut delay_line = DelayLine::new(8192, sample_rate);
+    let mut mip_map = MipMap::new(delay_line.get_size(), sample_rate);
+    write_sine(&mut delay_line, &mut mip_map, 8000., sample_rate);
+
+    let band_limit = mip_map.get_band_limit(4.);
+    for i in 0..20 {
+      let time = 20. + i as f32 * 3.3;
+      let actual = mip_map.read(&delay_line, time, Interpolation::Linear, band_limit);
+      assert_approximately_eq!(actual, 0., 2);
+    }
+  }
+}
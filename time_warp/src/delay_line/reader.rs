@@ -0,0 +1,64 @@
+use super::{BandLimit, DelayLine, Interpolation};
+
+/// Reads grains from the delay line, while crossfading from the previous delay line after a
+/// streamed file window was swapped in.
+#[derive(Clone, Copy)]
+pub struct DelayLineReader<'a> {
+  delay_line: &'a DelayLine,
+  // the previous delay line and the gain of the current one
+  previous: Option<(&'a DelayLine, f32)>,
+}
+
+impl<'a> DelayLineReader<'a> {
+  pub fn new(delay_line: &'a DelayLine, previous: Option<(&'a DelayLine, f32)>) -> Self {
+    Self {
+      delay_line,
+      previous,
+    }
+  }
+
+  pub fn read_band_limited(&self, time: f32, interp: Interpolation, band_limit: BandLimit) -> f32 {
+    let out = self.delay_line.read_band_limited(time, interp, band_limit);
+    match self.previous {
+      Some((previous, gain)) => {
+        let previous_out = previous.read_band_limited(time, interp, band_limit);
+        previous_out + (out - previous_out) * gain
+      }
+      None => out,
+    }
+  }
+
+  pub fn get_band_limit(&self, speed: f64) -> BandLimit {
+    self.delay_line.get_band_limit(speed)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::DelayLineReader,
+    crate::{
+      assert_approximately_eq,
+      delay_line::{BandLimit, DelayLine, Interpolation},
+    },
+  };
+
+  #[test]
+  fn should_crossfade_from_the_previous_delay_line() {
+    let mut delay_line = DelayLine::new(16, 1000.);
+    let mut previous_delay_line = DelayLine::new(16, 1000.);
+    for _ in 0..16 {
+      delay_line.write(1.);
+      previous_delay_line.write(-1.);
+    }
+
+    for (gain, expected) in [(0., -1.), (0.25, -0.5), (1., 1.)] {
+      let reader = DelayLineReader::new(&delay_line, Some((&previous_delay_line, gain)));
+      assert_approximately_eq!(
+        reader.read_band_limited(5., Interpolation::Linear, BandLimit::default()),
+        expected,
+        4
+      );
+    }
+  }
+}
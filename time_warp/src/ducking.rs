@@ -0,0 +1,60 @@
+use crate::envelope_follower::EnvelopeFollower;
+
+const ATTACK_TIME: f32 = 5.;
+
+/// Returns the gain to reduce the wet signal with while the input exceeds the threshold.
+pub struct Ducking {
+  envelope_follower: EnvelopeFollower,
+}
+
+impl Ducking {
+  pub fn new(sample_rate: f32) -> Self {
+    Self {
+      envelope_follower: EnvelopeFollower::new(sample_rate),
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.envelope_follower.reset();
+  }
+
+  pub fn process(&mut self, input: (f32, f32), threshold: f32, amount: f32, release: f32) -> f32 {
+    if threshold == 0. || amount == 0. {
+      return 1.;
+    }
+    let key = if input.0.abs().max(input.1.abs()) > threshold {
+      1.
+    } else {
+      0.
+    };
+    let reduction = self
+      .envelope_follower
+      .process((key, 0.), ATTACK_TIME, release);
+    1. - reduction * amount
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Ducking;
+
+  #[test]
+  fn should_duck_while_input_exceeds_threshold_and_recover_after_release() {
+    let mut ducking = Ducking::new(1000.);
+    let mut gain = 1.;
+    for _ in 0..100 {
+      gain = ducking.process((0.5, 0.), 0.1, 0.8, 50.);
+    }
+    assert!((gain - 0.2).abs() < 0.01);
+    for _ in 0..500 {
+      gain = ducking.process((0.05, 0.), 0.1, 0.8, 50.);
+    }
+    assert!(gain > 0.99);
+  }
+
+  #[test]
+  fn should_not_duck_when_off() {
+    let mut ducking = Ducking::new(1000.);
+    assert_eq!(ducking.process((1., 1.), 0., 1., 50.), 1.);
+  }
+}
@@ -0,0 +1,86 @@
+use crate::shared::float_ext::FloatExt;
+
+pub struct EnvelopeFollower {
+  sample_rate: f32,
+  z: f32,
+  prev_attack_time: f32,
+  prev_release_time: f32,
+  attack_coefficient: f32,
+  release_coefficient: f32,
+}
+
+impl EnvelopeFollower {
+  pub fn new(sample_rate: f32) -> Self {
+    Self {
+      sample_rate,
+      z: 0.,
+      prev_attack_time: 0.,
+      prev_release_time: 0.,
+      attack_coefficient: 0.,
+      release_coefficient: 0.,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.z = 0.;
+  }
+
+  pub fn process(&mut self, input: (f32, f32), attack_time: f32, release_time: f32) -> f32 {
+    self.set_coefficients(attack_time, release_time);
+    let x = input.0.abs().max(input.1.abs());
+    let coefficient = if x > self.z {
+      self.attack_coefficient
+    } else {
+      self.release_coefficient
+    };
+    self.z = x + (self.z - x) * coefficient;
+    self.z
+  }
+
+  fn set_coefficients(&mut self, attack_time: f32, release_time: f32) {
+    if attack_time != self.prev_attack_time {
+      self.attack_coefficient = self.get_coefficient(attack_time);
+      self.prev_attack_time = attack_time;
+    }
+    if release_time != self.prev_release_time {
+      self.release_coefficient = self.get_coefficient(release_time);
+      self.prev_release_time = release_time;
+    }
+  }
+
+  fn get_coefficient(&self, time: f32) -> f32 {
+    if time <= 0. {
+      0.
+    } else {
+      (-time.mstosamps(self.sample_rate).recip()).exp()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::EnvelopeFollower;
+
+  #[test]
+  fn should_follow_rising_and_falling_input() {
+    let mut envelope_follower = EnvelopeFollower::new(1000.);
+    let mut prev_envelope = 0.;
+    for _ in 0..10 {
+      let envelope = envelope_follower.process((1., -1.), 10., 100.);
+      assert!(envelope > prev_envelope && envelope <= 1.);
+      prev_envelope = envelope;
+    }
+    for _ in 0..10 {
+      let envelope = envelope_follower.process((0., 0.), 10., 100.);
+      assert!(envelope < prev_envelope && envelope >= 0.);
+      prev_envelope = envelope;
+    }
+  }
+
+  #[test]
+  fn should_pass_input_without_attack_and_release_time() {
+    let mut envelope_follower = EnvelopeFollower::new(1000.);
+    assert_eq!(envelope_follower.process((0.5, -0.8), 0., 0.), 0.8);
+    assert_eq!(envelope_follower.process((0.2, 0.1), 0., 0.), 0.2);
+  }
+}
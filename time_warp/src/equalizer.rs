@@ -0,0 +1,166 @@
+mod biquad;
+use {
+  biquad::Biquad,
+  std::f32::consts::{FRAC_1_SQRT_2, TAU},
+};
+
+const LOW_SHELF_FREQ: f32 = 250.;
+const HIGH_SHELF_FREQ: f32 = 4000.;
+const MID_Q: f32 = 0.7;
+
+/// Three band equalizer with a low shelf, a mid peak and a high shelf.
+pub struct Equalizer {
+  sample_rate: f32,
+  low_shelf: Biquad,
+  mid_peak: Biquad,
+  high_shelf: Biquad,
+  prev_gains: (f32, f32, f32),
+  prev_mid_freq: f32,
+  low_coefficients: ([f32; 3], [f32; 2]),
+  mid_coefficients: ([f32; 3], [f32; 2]),
+  high_coefficients: ([f32; 3], [f32; 2]),
+}
+
+impl Equalizer {
+  pub fn new(sample_rate: f32) -> Self {
+    let mut equalizer = Self {
+      sample_rate,
+      low_shelf: Biquad::new(),
+      mid_peak: Biquad::new(),
+      high_shelf: Biquad::new(),
+      prev_gains: (0., 0., 0.),
+      prev_mid_freq: 0.,
+      low_coefficients: ([1., 0., 0.], [0., 0.]),
+      mid_coefficients: ([1., 0., 0.], [0., 0.]),
+      high_coefficients: ([1., 0., 0.], [0., 0.]),
+    };
+    equalizer.set_coefficients(0., 0., 1000., 0.);
+    equalizer
+  }
+
+  pub fn reset(&mut self) {
+    self.low_shelf.reset();
+    self.mid_peak.reset();
+    self.high_shelf.reset();
+  }
+
+  /// Gains are in decibels.
+  pub fn process(
+    &mut self,
+    input: (f32, f32),
+    low: f32,
+    mid: f32,
+    mid_freq: f32,
+    high: f32,
+  ) -> (f32, f32) {
+    if low == 0. && mid == 0. && high == 0. {
+      return input;
+    }
+    self.set_coefficients(low, mid, mid_freq, high);
+    let low_shelf_out = self.low_shelf.process(input, self.low_coefficients);
+    let mid_peak_out = self.mid_peak.process(low_shelf_out, self.mid_coefficients);
+    self
+      .high_shelf
+      .process(mid_peak_out, self.high_coefficients)
+  }
+
+  fn set_coefficients(&mut self, low: f32, mid: f32, mid_freq: f32, high: f32) {
+    let (prev_low, prev_mid, prev_high) = self.prev_gains;
+    if low != prev_low {
+      self.low_coefficients = self.get_shelf_coefficients(LOW_SHELF_FREQ, low, false);
+    }
+    if mid != prev_mid || mid_freq != self.prev_mid_freq {
+      self.mid_coefficients = self.get_peak_coefficients(mid_freq, mid);
+      self.prev_mid_freq = mid_freq;
+    }
+    if high != prev_high {
+      self.high_coefficients = self.get_shelf_coefficients(HIGH_SHELF_FREQ, high, true);
+    }
+    self.prev_gains = (low, mid, high);
+  }
+
+  fn get_peak_coefficients(&self, freq: f32, gain: f32) -> ([f32; 3], [f32; 2]) {
+    let a = 10_f32.powf(gain / 40.);
+    let w0 = TAU * freq / self.sample_rate;
+    let alpha = w0.sin() / (2. * MID_Q);
+    let cos_w0 = w0.cos();
+
+    Self::normalize(
+      [1. + alpha * a, -2. * cos_w0, 1. - alpha * a],
+      [1. + alpha / a, -2. * cos_w0, 1. - alpha / a],
+    )
+  }
+
+  fn get_shelf_coefficients(
+    &self,
+    freq: f32,
+    gain: f32,
+    is_high_shelf: bool,
+  ) -> ([f32; 3], [f32; 2]) {
+    let a = 10_f32.powf(gain / 40.);
+    let w0 = TAU * freq / self.sample_rate;
+    let alpha = w0.sin() * 0.5 / FRAC_1_SQRT_2;
+    let cos_w0 = w0.cos();
+    let sqrt_a_alpha = 2. * a.sqrt() * alpha;
+    // the high shelf is the low shelf with the sign of cos(w0) flipped
+    let sign = if is_high_shelf { -1. } else { 1. };
+
+    Self::normalize(
+      [
+        a * ((a + 1.) - sign * (a - 1.) * cos_w0 + sqrt_a_alpha),
+        sign * 2. * a * ((a - 1.) - sign * (a + 1.) * cos_w0),
+        a * ((a + 1.) - sign * (a - 1.) * cos_w0 - sqrt_a_alpha),
+      ],
+      [
+        (a + 1.) + sign * (a - 1.) * cos_w0 + sqrt_a_alpha,
+        -sign * 2. * ((a - 1.) + sign * (a + 1.) * cos_w0),
+        (a + 1.) + sign * (a - 1.) * cos_w0 - sqrt_a_alpha,
+      ],
+    )
+  }
+
+  fn normalize(b: [f32; 3], a: [f32; 3]) -> ([f32; 3], [f32; 2]) {
+    let a0 = a[0].recip();
+    ([b[0] * a0, b[1] * a0, b[2] * a0], [a[1] * a0, a[2] * a0])
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Equalizer;
+  use std::f32::consts::TAU;
+
+  fn get_peak(equalizer: &mut Equalizer, freq: f32, gains: (f32, f32, f32)) -> f32 {
+    equalizer.reset();
+    (0..8000)
+      .map(|i| {
+        let x = (i as f32 * freq * TAU / 44100.).sin();
+        equalizer
+          .process((x, x), gains.0, gains.1, 1000., gains.2)
+          .0
+      })
+      .skip(4000)
+      .fold(0., |peak, x| x.abs().max(peak))
+  }
+
+  #[test]
+  fn should_boost_and_cut_each_band() {
+    let mut equalizer = Equalizer::new(44100.);
+    let gain = 2_f32; // 6 dB
+    let boost = 20. * gain.log10();
+    assert!((get_peak(&mut equalizer, 40., (boost, 0., 0.)) - gain).abs() < 0.05);
+    assert!((get_peak(&mut equalizer, 1000., (0., boost, 0.)) - gain).abs() < 0.05);
+    assert!((get_peak(&mut equalizer, 16000., (0., 0., boost)) - gain).abs() < 0.05);
+    assert!((get_peak(&mut equalizer, 40., (-boost, 0., 0.)) - gain.recip()).abs() < 0.05);
+    assert!((get_peak(&mut equalizer, 1000., (-boost, 0., 0.)) - 1.).abs() < 0.05);
+  }
+
+  #[test]
+  fn should_pass_input_when_flat() {
+    let mut equalizer = Equalizer::new(44100.);
+    assert_eq!(
+      equalizer.process((0.3, -0.2), 0., 0., 1000., 0.),
+      (0.3, -0.2)
+    );
+  }
+}
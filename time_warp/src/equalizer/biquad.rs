@@ -0,0 +1,29 @@
+pub struct Biquad {
+  z: [(f32, f32); 2],
+}
+
+impl Biquad {
+  pub fn new() -> Self {
+    Self { z: [(0., 0.); 2] }
+  }
+
+  pub fn reset(&mut self) {
+    self.z = [(0., 0.); 2];
+  }
+
+  /// Expects normalized coefficients `([b0, b1, b2], [a1, a2])`.
+  pub fn process(&mut self, input: (f32, f32), coefficients: ([f32; 3], [f32; 2])) -> (f32, f32) {
+    (
+      Self::apply_filter(&mut self.z[0], input.0, coefficients),
+      Self::apply_filter(&mut self.z[1], input.1, coefficients),
+    )
+  }
+
+  fn apply_filter(z: &mut (f32, f32), x: f32, coefficients: ([f32; 3], [f32; 2])) -> f32 {
+    let ([b0, b1, b2], [a1, a2]) = coefficients;
+    let y = b0 * x + z.0;
+    z.0 = b1 * x - a1 * y + z.1;
+    z.1 = b2 * x - a2 * y;
+    y
+  }
+}
@@ -1,4 +1,7 @@
+mod multimode_filter;
 mod one_pole_filter;
+mod state_variable_filter;
+pub use multimode_filter::MultimodeFilter;
 use one_pole_filter::{FilterType, OnePoleFilter};
 
 pub struct Filter {
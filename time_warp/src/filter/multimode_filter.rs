@@ -0,0 +1,159 @@
+use super::state_variable_filter::StateVariableFilter;
+use crate::params::{FilterMode, FilterSlope};
+use std::f32::consts::{PI, SQRT_2};
+
+const MIN_CUTOFF_FREQ: f32 = 20.;
+const MIN_DAMPING: f32 = 0.05;
+
+pub struct MultimodeFilter {
+  sample_rate: f32,
+  filters: [[StateVariableFilter; 2]; 2],
+  mode: FilterMode,
+  slope: FilterSlope,
+  prev_cutoff_freq: f32,
+  prev_resonance: f32,
+  coefficients: [f32; 4],
+  first_stage_coefficients: [f32; 4],
+}
+
+impl MultimodeFilter {
+  pub fn new(sample_rate: f32) -> Self {
+    Self {
+      sample_rate,
+      filters: [
+        [StateVariableFilter::new(), StateVariableFilter::new()],
+        [StateVariableFilter::new(), StateVariableFilter::new()],
+      ],
+      mode: FilterMode::Lowpass,
+      slope: FilterSlope::Db12,
+      prev_cutoff_freq: 0.,
+      prev_resonance: -1.,
+      coefficients: [0.; 4],
+      first_stage_coefficients: [0.; 4],
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self
+      .filters
+      .iter_mut()
+      .flatten()
+      .for_each(|filter| filter.reset());
+  }
+
+  pub fn set(&mut self, mode: FilterMode, slope: FilterSlope, cutoff_freq: f32, resonance: f32) {
+    self.mode = mode;
+    self.slope = slope;
+    let cutoff_freq = cutoff_freq.clamp(MIN_CUTOFF_FREQ, self.sample_rate * 0.49);
+    if cutoff_freq == self.prev_cutoff_freq && resonance == self.prev_resonance {
+      return;
+    }
+    let g = (PI * cutoff_freq / self.sample_rate).tan();
+    self.coefficients = Self::get_coefficients(g, (2. - 2. * resonance).max(MIN_DAMPING));
+    // the first stage of the 24 dB slope is a butterworth stage, so only one stage resonates
+    self.first_stage_coefficients = Self::get_coefficients(g, SQRT_2);
+    self.prev_cutoff_freq = cutoff_freq;
+    self.prev_resonance = resonance;
+  }
+
+  pub fn process_mono(&mut self, input: f32) -> f32 {
+    self.process_channel(0, input)
+  }
+
+  pub fn process(&mut self, input: (f32, f32)) -> (f32, f32) {
+    (
+      self.process_channel(0, input.0),
+      self.process_channel(1, input.1),
+    )
+  }
+
+  fn process_channel(&mut self, channel: usize, input: f32) -> f32 {
+    let [first_stage, second_stage] = &mut self.filters[channel];
+    match self.slope {
+      FilterSlope::Db12 => Self::get_mode_output(first_stage, input, self.coefficients, self.mode),
+      FilterSlope::Db24 => {
+        let first_stage_out =
+          Self::get_mode_output(first_stage, input, self.first_stage_coefficients, self.mode);
+        Self::get_mode_output(second_stage, first_stage_out, self.coefficients, self.mode)
+      }
+    }
+  }
+
+  fn get_mode_output(
+    filter: &mut StateVariableFilter,
+    input: f32,
+    coefficients: [f32; 4],
+    mode: FilterMode,
+  ) -> f32 {
+    let output = filter.process(input, coefficients);
+    match mode {
+      FilterMode::Lowpass => output.lowpass,
+      FilterMode::Highpass => output.highpass,
+      // scaled by the damping, so the peak gain is unity at any resonance
+      FilterMode::Bandpass => output.bandpass * coefficients[0],
+      FilterMode::Notch => output.notch,
+    }
+  }
+
+  fn get_coefficients(g: f32, k: f32) -> [f32; 4] {
+    let a1 = (1. + g * (g + k)).recip();
+    let a2 = g * a1;
+    let a3 = g * a2;
+    [k, a1, a2, a3]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::MultimodeFilter;
+  use crate::params::{FilterMode, FilterSlope};
+  use std::f32::consts::TAU;
+
+  fn get_peak(filter: &mut MultimodeFilter, freq: f32) -> f32 {
+    (0..4000)
+      .map(|i| filter.process_mono((i as f32 * freq * TAU / 44100.).sin()))
+      .skip(2000)
+      .fold(0., |peak, x| x.abs().max(peak))
+  }
+
+  #[test]
+  fn should_attenuate_above_cutoff_with_lowpass() {
+    let mut filter = MultimodeFilter::new(44100.);
+    filter.set(FilterMode::Lowpass, FilterSlope::Db12, 500., 0.);
+    let peak_12db = get_peak(&mut filter, 8000.);
+    filter.reset();
+    filter.set(FilterMode::Lowpass, FilterSlope::Db24, 500., 0.);
+    let peak_24db = get_peak(&mut filter, 8000.);
+    assert!(peak_12db < 0.01);
+    assert!(peak_24db < peak_12db);
+    filter.reset();
+    assert!(get_peak(&mut filter, 50.) > 0.9);
+  }
+
+  #[test]
+  fn should_reject_the_cutoff_frequency_with_notch() {
+    let mut filter = MultimodeFilter::new(44100.);
+    filter.set(FilterMode::Notch, FilterSlope::Db12, 1000., 0.5);
+    assert!(get_peak(&mut filter, 1000.) < 0.05);
+    filter.reset();
+    filter.set(FilterMode::Bandpass, FilterSlope::Db12, 1000., 0.5);
+    assert!(get_peak(&mut filter, 1000.) > 0.9);
+  }
+
+  #[test]
+  fn should_boost_the_cutoff_frequency_with_resonance() {
+    let mut filter = MultimodeFilter::new(44100.);
+    filter.set(FilterMode::Lowpass, FilterSlope::Db12, 1000., 0.9);
+    assert!(get_peak(&mut filter, 1000.) > 2.);
+  }
+
+  #[test]
+  fn should_keep_the_bandpass_peak_at_unity() {
+    let mut filter = MultimodeFilter::new(44100.);
+    filter.set(FilterMode::Bandpass, FilterSlope::Db12, 1000., 0.9);
+    let peak = get_peak(&mut filter, 1000.);
+    assert!(peak > 0.95 && peak < 1.05);
+    filter.reset();
+    assert!(get_peak(&mut filter, 100.) < 0.1);
+  }
+}
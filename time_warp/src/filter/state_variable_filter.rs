@@ -0,0 +1,43 @@
+/// Trapezoidal integrated state variable filter, which stays stable while the cutoff is modulated.
+pub struct StateVariableFilter {
+  ic1eq: f32,
+  ic2eq: f32,
+}
+
+pub struct StateVariableFilterOutput {
+  pub lowpass: f32,
+  pub bandpass: f32,
+  pub highpass: f32,
+  pub notch: f32,
+}
+
+impl StateVariableFilter {
+  pub fn new() -> Self {
+    Self {
+      ic1eq: 0.,
+      ic2eq: 0.,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.ic1eq = 0.;
+    self.ic2eq = 0.;
+  }
+
+  /// Expects the coefficients `[k, a1, a2, a3]`, where `k` is the damping.
+  pub fn process(&mut self, input: f32, coefficients: [f32; 4]) -> StateVariableFilterOutput {
+    let [k, a1, a2, a3] = coefficients;
+    let v3 = input - self.ic2eq;
+    let v1 = a1 * self.ic1eq + a2 * v3;
+    let v2 = self.ic2eq + a2 * self.ic1eq + a3 * v3;
+    self.ic1eq = 2. * v1 - self.ic1eq;
+    self.ic2eq = 2. * v2 - self.ic2eq;
+
+    StateVariableFilterOutput {
+      lowpass: v2,
+      bandpass: v1,
+      highpass: input - k * v1 - v2,
+      notch: input - k * v1,
+    }
+  }
+}
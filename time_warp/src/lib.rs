@@ -1,9 +1,17 @@
 mod delay_line;
+mod ducking;
+mod envelope_follower;
+mod equalizer;
 mod filter;
 mod mix;
 mod notes;
+mod oversampler;
 mod params;
+mod reverb;
+mod saturation;
+mod tape;
 mod voices;
+mod wow_flutter;
 pub mod shared {
   pub mod delta;
   pub mod float_ext;
@@ -12,65 +20,300 @@ pub mod shared {
   pub mod tuple_ext;
 }
 mod audio_file_processor;
+mod controls;
 
 use {
-  crate::shared::tuple_ext::TupleExt, filter::Filter, mix::Mix, notes::Note, params::Smoother,
-  shared::float_ext::FloatExt, voices::Voices,
+  crate::shared::tuple_ext::TupleExt,
+  controls::Controls,
+  delay_line::DelayLineReader,
+  ducking::Ducking,
+  envelope_follower::EnvelopeFollower,
+  equalizer::Equalizer,
+  filter::{Filter, MultimodeFilter},
+  mix::Mix,
+  notes::Note,
+  oversampler::{LatencyCompensation, Oversampler, MAX_LATENCY},
+  params::Smoother,
+  reverb::Reverb,
+  shared::float_ext::FloatExt,
+  std::mem,
+  tape::Tape,
+  voices::{Shimmer, Voices},
+  wow_flutter::WowFlutter,
 };
 pub use {
-  audio_file_processor::{AudioFileData, AudioFileProcessor},
+  audio_file_processor::{
+    AudioFile, AudioFileData, AudioFileProcessingError, AudioFileProcessor, FileStream,
+    Normalization, ResamplerQuality, SampleProcessing, SampleRateConversion, StreamWindow,
+  },
   delay_line::{DelayLine, Interpolation},
   notes::Notes,
-  params::{Params, SampleMode},
+  oversampler::Oversampling,
+  params::{
+    DuckingSettings, EnvelopeDestination, EnvelopeSettings, EqSettings, FilterMode,
+    FilterPlacement, FilterSettings, FilterSlope, InputSource, Params, QualitySettings,
+    RecordSettings, ReverbSettings, SampleMode, SaturationSettings, Settings, ShimmerInterval,
+    ShimmerSettings, TapeSettings, TapeSpeed, WowFlutterSettings,
+  },
+  saturation::Saturation,
 };
 
 const FADE_TIME: f64 = 5.;
 pub const MIN_DELAY_TIME: f32 = 10.; // double of FADE_TIME
-const MAX_DELAY_TIME: f32 = 60000.;
+pub const DEFAULT_MAX_DELAY_TIME: f32 = 60000.;
 pub const MIN_DENSITY: f64 = 1.;
 pub const MAX_DENSITY: f64 = 8.;
 pub const CENTER_GRAIN_DURATION: f32 = 500.;
 pub const MAX_VOICE_COUNT: usize = 8;
+const FILTER_MODULATION_OCTAVES: f32 = 4.;
+const CONTROL_BLOCK_SIZE: usize = 32;
+/// Delay lines up to this size get a mip map for band limited reads, which almost doubles their
+/// memory. Longer buffers are read without band limiting.
+const MAX_BAND_LIMITED_SIZE: usize = 1 << 23;
 
 pub struct TimeWarp {
   delay_line: DelayLine,
+  // the window of a streamed file that's faded out, along with the gain of the new window
+  previous_delay_line: Option<DelayLine>,
+  window_crossfade: f32,
   voices: Voices,
+  shimmer: Shimmer,
   filter: Filter,
+  multimode_filter: MultimodeFilter,
+  reverb: Reverb,
+  equalizer: Equalizer,
   mix: Mix,
+  envelope_follower: EnvelopeFollower,
+  ducking: Ducking,
+  tape: Tape,
+  wow_flutter: WowFlutter,
+  oversampler: Oversampler,
+  latency_compensation: LatencyCompensation<{ MAX_LATENCY + 1 }>,
+  controls: Controls,
+  sample_rate: f32,
 }
 
 impl TimeWarp {
-  pub fn new(sample_rate: f32) -> Self {
+  pub fn new(sample_rate: f32, max_delay_time: f32) -> Self {
     Self {
-      delay_line: DelayLine::new(
-        (sample_rate * (MAX_DELAY_TIME + FADE_TIME as f32) / 1000.) as usize,
-        sample_rate,
-      ),
+      delay_line: Self::create_delay_line(sample_rate, max_delay_time),
+      previous_delay_line: None,
+      window_crossfade: 1.,
       voices: Voices::new(sample_rate),
+      shimmer: Shimmer::new(sample_rate),
       filter: Filter::new(sample_rate),
+      multimode_filter: MultimodeFilter::new(sample_rate),
+      reverb: Reverb::new(sample_rate),
+      equalizer: Equalizer::new(sample_rate),
       mix: Mix::new(),
+      envelope_follower: EnvelopeFollower::new(sample_rate),
+      ducking: Ducking::new(sample_rate),
+      tape: Tape::new(sample_rate, max_delay_time),
+      wow_flutter: WowFlutter::new(sample_rate),
+      oversampler: Oversampler::new(),
+      latency_compensation: LatencyCompensation::new(),
+      controls: Controls::default(),
+      sample_rate,
     }
   }
 
   pub fn reset(&mut self) {
     self.filter.reset();
+    self.multimode_filter.reset();
+    self.reverb.reset();
+    self.equalizer.reset();
     self.voices.reset();
+    self.shimmer.reset();
+    self.envelope_follower.reset();
+    self.ducking.reset();
+    self.tape.reset();
+    self.wow_flutter.reset();
+    self.oversampler.reset();
+    self.latency_compensation.reset();
   }
 
   pub fn reset_delay_line(&mut self) {
     self.delay_line.reset();
+    self.previous_delay_line = None;
   }
 
   pub fn process(
     &mut self,
     input: (f32, f32),
+    sidechain: Option<(f32, f32)>,
     params: &mut Params,
     notes: &mut Vec<Note>,
   ) -> (f32, f32) {
+    let (dry_out, wet_out) = self.process_split(input, sidechain, params, notes);
+    dry_out.add(wet_out)
+  }
+
+  /// Processes a block of samples, where the channel counts of `inputs` and `outputs` select
+  /// between the stereo, mono-to-stereo and mono layout. The wet signal goes to `wet_outputs`
+  /// when they have as many channels as `outputs`, otherwise it's added to `outputs`. A sidechain
+  /// with fewer channels than the inputs is ignored. The control rate values are read once every
+  /// `CONTROL_BLOCK_SIZE` samples.
+  pub fn process_block(
+    &mut self,
+    inputs: &[&[f32]],
+    outputs: &mut [&mut [f32]],
+    wet_outputs: Option<&mut [&mut [f32]]>,
+    sidechain: Option<&[&[f32]]>,
+    params: &mut Params,
+    notes: &mut Vec<Note>,
+  ) {
+    let block_size = outputs.iter().map(|output| output.len()).min().unwrap_or(0);
+    let has_valid_channels =
+      |channels: &[&[f32]]| channels.iter().all(|channel| channel.len() >= block_size);
+    if !matches!((inputs.len(), outputs.len()), (2, 2) | (1, 2) | (1, 1))
+      || !has_valid_channels(inputs)
+    {
+      debug_assert!(
+        false,
+        "Only stereo, mono-to-stereo and mono channel layouts are supported."
+      );
+      Self::pass_through(inputs, outputs);
+      return;
+    }
+    let sidechain =
+      sidechain.filter(|channels| channels.len() >= inputs.len() && has_valid_channels(channels));
+    let mut wet_outputs = wet_outputs.filter(|channels| {
+      channels.len() == outputs.len() && channels.iter().all(|channel| channel.len() >= block_size)
+    });
+    let is_stereo_input = inputs.len() == 2;
+    let read_frame = |channels: &[&[f32]], index: usize| {
+      if is_stereo_input {
+        (channels[0][index], channels[1][index])
+      } else {
+        (channels[0][index], channels[0][index])
+      }
+    };
+
+    for index in 0..block_size {
+      let is_block_start = index % CONTROL_BLOCK_SIZE == 0;
+      if is_block_start {
+        self.controls = Controls::read(params, CONTROL_BLOCK_SIZE.min(block_size - index));
+      }
+      let (dry_out, wet_out) = self.process_frame(
+        read_frame(inputs, index),
+        sidechain.map(|channels| read_frame(channels, index)),
+        params,
+        notes,
+        is_block_start,
+      );
+      // a mono output gets the stereo grains folded down
+      let wet_out = if outputs.len() == 1 {
+        let wet_out = (wet_out.0 + wet_out.1) * 0.5;
+        (wet_out, wet_out)
+      } else {
+        wet_out
+      };
+      match wet_outputs.as_mut() {
+        Some(wet_outputs) => {
+          Self::write_frame(outputs, index, dry_out);
+          Self::write_frame(wet_outputs, index, wet_out);
+        }
+        None => Self::write_frame(outputs, index, dry_out.add(wet_out)),
+      }
+    }
+  }
+
+  /// Returns the dry and the wet signal separately, so they can be routed to different outputs.
+  pub fn process_split(
+    &mut self,
+    input: (f32, f32),
+    sidechain: Option<(f32, f32)>,
+    params: &mut Params,
+    notes: &mut Vec<Note>,
+  ) -> ((f32, f32), (f32, f32)) {
+    self.controls = Controls::read(params, 1);
+    self.process_frame(input, sidechain, params, notes, true)
+  }
+
+  /// Processes a mono input into a stereo output. The input is recorded and played back at the
+  /// same level as a centered stereo input.
+  pub fn process_mono_to_stereo(
+    &mut self,
+    input: f32,
+    sidechain: Option<f32>,
+    params: &mut Params,
+    notes: &mut Vec<Note>,
+  ) -> (f32, f32) {
+    self.process(
+      (input, input),
+      sidechain.map(|sidechain| (sidechain, sidechain)),
+      params,
+      notes,
+    )
+  }
+
+  /// Processes a mono input into a mono output, where the stereo grains are folded down.
+  pub fn process_mono(
+    &mut self,
+    input: f32,
+    sidechain: Option<f32>,
+    params: &mut Params,
+    notes: &mut Vec<Note>,
+  ) -> f32 {
+    let (dry_out, wet_out) = self.process_split(
+      (input, input),
+      sidechain.map(|sidechain| (sidechain, sidechain)),
+      params,
+      notes,
+    );
+    dry_out.0 + (wet_out.0 + wet_out.1) * 0.5
+  }
+
+  fn write_frame(channels: &mut [&mut [f32]], index: usize, frame: (f32, f32)) {
+    channels[0][index] = frame.0;
+    if let Some(channel) = channels.get_mut(1) {
+      channel[index] = frame.1;
+    }
+  }
+
+  fn pass_through(inputs: &[&[f32]], outputs: &mut [&mut [f32]]) {
+    for (index, output) in outputs.iter_mut().enumerate() {
+      match inputs.get(index).or(inputs.first()) {
+        Some(input) if input.len() >= output.len() => {
+          output.copy_from_slice(&input[..output.len()])
+        }
+        _ => output.fill(0.),
+      }
+    }
+  }
+
+  fn process_frame(
+    &mut self,
+    input: (f32, f32),
+    sidechain: Option<(f32, f32)>,
+    params: &mut Params,
+    notes: &mut Vec<Note>,
+    is_block_start: bool,
+  ) -> ((f32, f32), (f32, f32)) {
+    // without a connected sidechain both sources fall back to the main input
+    let sidechain = sidechain.unwrap_or(input);
+    // `write_to_delay` reads the repeats earlier by the oversampling latency, so that only keeps the
+    // delay time. The host still moves the whole output earlier by the reported latency, so the dry
+    // path has to be delayed as much as the wet path to stay aligned with it and with other tracks.
+    // Delaying the inputs does both, and keeps the record threshold and envelope in sync with them.
+    let (input, sidechain) = self
+      .latency_compensation
+      .process((input, sidechain), params.oversampling.get_latency());
+    let record_input = match params.record_source {
+      InputSource::Main => input,
+      InputSource::Sidechain => sidechain,
+    };
+    let envelope_input = match params.envelope_source {
+      InputSource::Main => input,
+      InputSource::Sidechain => sidechain,
+    };
+    params.process_record_threshold(record_input);
+    if let Some(factor) = self.tape.process(params.tape_speed, params.get_loop_time()) {
+      params.scale_loop_duration(factor);
+    }
     let Params {
       scan,
       spray,
-      size,
       stereo,
       speed,
       stretch,
@@ -78,26 +321,79 @@ impl TimeWarp {
       sync_position,
       should_reset_playback,
       start_offset_phase,
+      envelope_attack,
+      envelope_release,
+      envelope_amount,
+      envelope_destination,
+      gate_threshold,
+      duck_threshold,
+      duck_amount,
+      duck_release,
+      filter_placement,
+      filter_mode,
+      filter_slope,
+      reverb_decay,
+      reverb_to_delay,
+      eq_mid_freq,
+      interpolation,
       ..
     } = *params;
 
-    let density = params.density.next();
+    // gains and the delay time change at every sample to prevent zipper noise
     let recording_gain = params.recording_gain.next();
     let playback_gain = params.playback_gain.next();
     let time = params.time.next();
-    let recycle = params.recycle.next();
-    let feedback = params.feedback.next();
     let dry = params.dry.next();
     let wet = params.wet.next();
-    let attack = params.attack.next();
-    let decay = params.decay.next();
-    let sustain = params.sustain.next();
-    let release = params.release.next();
 
+    let envelope =
+      self
+        .envelope_follower
+        .process(envelope_input, envelope_attack, envelope_release);
+    let is_gated = gate_threshold > 0. && envelope < gate_threshold;
+    let modulation = envelope.min(1.) * envelope_amount;
+    if is_block_start {
+      Self::apply_control_modulation(&mut self.controls, modulation, envelope_destination);
+      if filter_placement != FilterPlacement::Off {
+        self.multimode_filter.set(
+          filter_mode,
+          filter_slope,
+          self.controls.filter_cutoff,
+          self.controls.filter_resonance,
+        );
+      }
+      self.voices.set_grain_settings(
+        self.controls.size,
+        time,
+        self.controls.density as f64,
+        interpolation,
+      );
+    }
+    let (wet, recording_gain) =
+      Self::apply_gain_modulation(modulation, envelope_destination, wet, recording_gain);
+    let Controls {
+      density,
+      wow,
+      flutter,
+      reverb_size,
+      reverb_mix,
+      eq_low,
+      eq_mid,
+      eq_high,
+      attack,
+      decay,
+      sustain,
+      release,
+      ..
+    } = self.controls;
+
+    let previous_delay_line = self
+      .previous_delay_line
+      .as_ref()
+      .map(|delay_line| (delay_line, self.window_crossfade));
     let (grains_out, grains_gain) = self.voices.process(
-      &self.delay_line,
+      &DelayLineReader::new(&self.delay_line, previous_delay_line),
       notes,
-      size,
       time,
       density as f64,
       stereo,
@@ -113,57 +409,224 @@ impl TimeWarp {
       release,
       should_reset_playback,
       start_offset_phase,
+      is_gated,
     );
     let gain_compensation = if grains_gain == 0. {
       0.
     } else {
       grains_gain.recip().sqrt()
     };
+    self.advance_window_crossfade();
     let grains_out = grains_out.multiply(playback_gain * gain_compensation);
-    self.write_to_delay(input, time, grains_out, recycle, feedback, recording_gain);
-    let output = input.multiply(dry).add(grains_out.multiply(wet));
+    let grains_out = if filter_placement == FilterPlacement::Grains {
+      self.multimode_filter.process(grains_out)
+    } else {
+      grains_out
+    };
+    let reverb_out = self
+      .reverb
+      .process(grains_out, reverb_size, reverb_decay, reverb_mix);
+    let wow_flutter_time = self.wow_flutter.process(wow, flutter);
+    self.write_to_delay(
+      record_input,
+      time,
+      if reverb_to_delay {
+        reverb_out
+      } else {
+        grains_out
+      },
+      recording_gain,
+      wow_flutter_time,
+      params,
+    );
+    // the tone stage only shapes the wet output, so the repeats keep their character
+    let wet_out = self
+      .equalizer
+      .process(reverb_out, eq_low, eq_mid, eq_mid_freq, eq_high);
+    let duck_gain = self
+      .ducking
+      .process(envelope_input, duck_threshold, duck_amount, duck_release);
     params.settle();
 
-    output
+    (input.multiply(dry), wet_out.multiply(wet * duck_gain))
   }
 
-  pub fn get_delay_line_size(&self) -> usize {
-    self.delay_line.get_size()
+  /// Allocates a delay line that holds `max_delay_time` milliseconds, so a worker thread can
+  /// build it and hand it over to `set_delay_line`.
+  pub fn create_delay_line(sample_rate: f32, max_delay_time: f32) -> DelayLine {
+    let length = (sample_rate * (max_delay_time + FADE_TIME as f32) / 1000.) as usize;
+    if Self::is_band_limited(length) {
+      DelayLine::new_band_limited(length, sample_rate)
+    } else {
+      DelayLine::new(length, sample_rate)
+    }
+  }
+
+  /// Creates a delay line that holds `values`, so a worker thread can build it, including its mip
+  /// map, and hand it over to `set_delay_line`.
+  pub fn create_delay_line_from_values(
+    values: Vec<f32>,
+    write_pointer_index: usize,
+    sample_rate: f32,
+  ) -> DelayLine {
+    let is_band_limited = Self::is_band_limited(values.len());
+    DelayLine::from_values(values, write_pointer_index, sample_rate, is_band_limited)
   }
 
-  pub fn set_delay_line_values(&mut self, values: Vec<f32>, write_pointer_index: usize) {
-    self.delay_line.set_values(values);
-    self.delay_line.set_write_pointer(write_pointer_index);
+  fn is_band_limited(length: usize) -> bool {
+    length.next_power_of_two() <= MAX_BAND_LIMITED_SIZE
+  }
+
+  pub fn set_delay_line(&mut self, delay_line: DelayLine, max_delay_time: f32) {
+    self.delay_line = delay_line;
+    self.previous_delay_line = None;
+    self.tape.set_max_delay_time(max_delay_time);
+    self.tape.reset();
+  }
+
+  /// Swaps in the next window of a streamed file. The grains crossfade from the previous window,
+  /// because they're at a different part of the file in the new window.
+  pub fn set_stream_window(&mut self, delay_line: DelayLine) {
+    self.previous_delay_line = Some(mem::replace(&mut self.delay_line, delay_line));
+    self.window_crossfade = 0.;
+  }
+
+  pub fn get_delay_line_size(&self) -> usize {
+    self.delay_line.get_size()
   }
 
   pub fn get_filter(&mut self) -> &mut Filter {
     &mut self.filter
   }
 
+  /// Modulates the control rate values, which happens once per block.
+  fn apply_control_modulation(
+    controls: &mut Controls,
+    modulation: f32,
+    envelope_destination: EnvelopeDestination,
+  ) {
+    if modulation == 0. {
+      return;
+    }
+    match envelope_destination {
+      EnvelopeDestination::Density => {
+        controls.density = (controls.density + modulation * (MAX_DENSITY - MIN_DENSITY) as f32)
+          .clamp(MIN_DENSITY as f32, MAX_DENSITY as f32)
+      }
+      EnvelopeDestination::Size => controls.size = (controls.size + modulation).clamp(0., 1.),
+      EnvelopeDestination::FilterCutoff => {
+        controls.filter_cutoff *= 2_f32.powf(modulation * FILTER_MODULATION_OCTAVES)
+      }
+      EnvelopeDestination::Wet | EnvelopeDestination::RecordingGain => (),
+    }
+  }
+
+  /// Modulates the gains, which happens at every sample.
+  fn apply_gain_modulation(
+    modulation: f32,
+    envelope_destination: EnvelopeDestination,
+    wet: f32,
+    recording_gain: f32,
+  ) -> (f32, f32) {
+    match envelope_destination {
+      EnvelopeDestination::Wet => (wet * (1. + modulation), recording_gain),
+      EnvelopeDestination::RecordingGain => (wet, (recording_gain + modulation).clamp(0., 1.)),
+      _ => (wet, recording_gain),
+    }
+  }
+
+  fn advance_window_crossfade(&mut self) {
+    if self.previous_delay_line.is_some() {
+      self.window_crossfade += (FADE_TIME as f32).mstosamps(self.sample_rate).recip();
+      if self.window_crossfade >= 1. {
+        self.previous_delay_line = None;
+      }
+    }
+  }
+
+  /// The control rate values come from `self.controls`, the modes from `params`.
   fn write_to_delay(
     &mut self,
     input: (f32, f32),
     time: f32,
     grains_out: (f32, f32),
-    recycle: f32,
-    feedback: f32,
     recording_gain: f32,
+    wow_flutter_time: f32,
+    params: &Params,
   ) {
+    let Params {
+      shimmer_speed,
+      interpolation,
+      oversampling,
+      ..
+    } = *params;
+    let Controls {
+      feedback, shimmer, ..
+    } = self.controls;
     let input = input.0 + input.1;
     let grains_out = grains_out.0 + grains_out.1;
-    let delay_out = self.delay_line.read(time, Interpolation::Linear);
-    let feedback = self.get_feedback(delay_out, grains_out, recycle, feedback);
+    let time = self.tape.get_time(time);
+    let delay_out = self.delay_line.read(time, interpolation);
+    // only the repeats are modulated, so a loop that isn't overdubbed stays intact
+    // and they're read earlier by the oversampling latency, so they still repeat at the delay time
+    let feedback_time =
+      time + wow_flutter_time - oversampling.get_latency() as f32 * 1000. / self.sample_rate;
+    let feedback_delay_out = if feedback_time == time {
+      delay_out
+    } else {
+      self.delay_line.read(feedback_time, interpolation)
+    };
+    let shimmer_out = if shimmer > 0. && feedback > 0. {
+      self.shimmer.process(
+        &self.delay_line,
+        feedback_time,
+        shimmer_speed,
+        interpolation,
+      )
+    } else {
+      0.
+    };
+    let feedback = self.get_feedback(feedback_delay_out, grains_out, shimmer_out, params);
     let delay_in = self
       .mix
       .process(delay_out, input + feedback, recording_gain);
     self.delay_line.write(delay_in);
   }
 
-  fn get_feedback(&mut self, delay_out: f32, grains_out: f32, recycle: f32, feedback: f32) -> f32 {
+  fn get_feedback(
+    &mut self,
+    delay_out: f32,
+    grains_out: f32,
+    shimmer_out: f32,
+    params: &Params,
+  ) -> f32 {
+    let Params {
+      saturation,
+      filter_placement,
+      oversampling,
+      ..
+    } = *params;
+    let Controls {
+      recycle,
+      feedback,
+      shimmer,
+      drive,
+      ..
+    } = self.controls;
     if feedback == 0. {
       return 0.;
     }
-    let feedback_signal = delay_out.mix(grains_out, recycle) * feedback;
-    self.filter.process(feedback_signal.clamp(-1., 1.))
+    let feedback_signal = delay_out.mix(grains_out, recycle).mix(shimmer_out, shimmer) * feedback;
+    let saturation_out = self
+      .oversampler
+      .process(feedback_signal, oversampling, |x| {
+        saturation.process(x, drive)
+      });
+    let filter_out = self.filter.process(saturation_out);
+    if filter_placement == FilterPlacement::Feedback {
+      self.multimode_filter.process_mono(filter_out)
+    } else {
+      filter_out
+    }
   }
 }
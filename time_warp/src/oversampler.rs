@@ -0,0 +1,127 @@
+mod halfband;
+mod latency_compensation;
+use halfband::Halfband;
+pub use latency_compensation::LatencyCompensation;
+
+pub const MAX_LATENCY: usize = 19;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Oversampling {
+  Off,
+  X2,
+  X4,
+}
+
+impl Oversampling {
+  /// The delay in samples caused by the up- and downsampling filters.
+  pub fn get_latency(&self) -> usize {
+    match self {
+      Oversampling::Off => 0,
+      Oversampling::X2 => 15,
+      Oversampling::X4 => MAX_LATENCY,
+    }
+  }
+}
+
+/// Runs a non-linear process at two or four times the sample rate, to keep its harmonics from
+/// folding back below the Nyquist frequency.
+pub struct Oversampler {
+  first_stage: Halfband<31>,
+  second_stage: Halfband<17>,
+}
+
+impl Oversampler {
+  pub fn new() -> Self {
+    Self {
+      first_stage: Halfband::new(),
+      second_stage: Halfband::new(),
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.first_stage.reset();
+    self.second_stage.reset();
+  }
+
+  pub fn process(
+    &mut self,
+    input: f32,
+    oversampling: Oversampling,
+    mut callback: impl FnMut(f32) -> f32,
+  ) -> f32 {
+    let Self {
+      first_stage,
+      second_stage,
+    } = self;
+
+    match oversampling {
+      Oversampling::Off => callback(input),
+      Oversampling::X2 => {
+        let upsampled = first_stage.upsample(input).map(callback);
+        first_stage.downsample(upsampled)
+      }
+      Oversampling::X4 => {
+        let upsampled = first_stage.upsample(input).map(|x| {
+          let upsampled = second_stage.upsample(x).map(&mut callback);
+          second_stage.downsample(upsampled)
+        });
+        first_stage.downsample(upsampled)
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::{Oversampler, Oversampling},
+    crate::assert_approximately_eq,
+    std::f32::consts::TAU,
+  };
+
+  #[test]
+  fn should_delay_the_signal_by_the_latency() {
+    for oversampling in [Oversampling::Off, Oversampling::X2, Oversampling::X4] {
+      let mut oversampler = Oversampler::new();
+      let latency = oversampling.get_latency();
+      let input: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.01 * TAU).sin()).collect();
+      let output: Vec<f32> = input
+        .iter()
+        .map(|x| oversampler.process(*x, oversampling, |x| x))
+        .collect();
+
+      for i in 100..1000 {
+        assert_approximately_eq!(output[i], input[i - latency], 3);
+      }
+    }
+  }
+
+  #[test]
+  fn should_reduce_aliasing() {
+    let sample_rate = 44100.;
+    let freq = 5000.;
+    let get_aliasing = |oversampling: Oversampling| {
+      let mut oversampler = Oversampler::new();
+      let output: Vec<f32> = (0..4410)
+        .map(|i| {
+          let x = (i as f32 * freq / sample_rate * TAU).sin();
+          oversampler.process(x, oversampling, |x| (x * 4.).clamp(-1., 1.))
+        })
+        .collect();
+      // the 9th harmonic at 45 kHz folds back to 900 Hz
+      let alias_freq = 9. * freq - sample_rate;
+      let (re, im) = output
+        .iter()
+        .skip(441)
+        .enumerate()
+        .fold((0., 0.), |(re, im), (i, y)| {
+          let phase = i as f32 * alias_freq / sample_rate * TAU;
+          (re + y * phase.cos(), im + y * phase.sin())
+        });
+      (re * re + im * im).sqrt() / 3969.
+    };
+
+    assert!(get_aliasing(Oversampling::X2) < get_aliasing(Oversampling::Off) * 0.5);
+    assert!(get_aliasing(Oversampling::X4) < get_aliasing(Oversampling::X2));
+  }
+}
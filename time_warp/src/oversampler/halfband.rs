@@ -0,0 +1,85 @@
+/// A Blackman-windowed halfband lowpass, which doubles or halves the sample rate.
+#[derive(Clone)]
+pub struct Halfband<const TAPS: usize> {
+  coefficients: [f32; TAPS],
+  upsample_history: [f32; TAPS],
+  upsample_index: usize,
+  downsample_history: [f32; TAPS],
+  downsample_index: usize,
+}
+
+impl<const TAPS: usize> Halfband<TAPS> {
+  pub fn new() -> Self {
+    Self {
+      coefficients: Self::get_coefficients(),
+      upsample_history: [0.; TAPS],
+      upsample_index: 0,
+      downsample_history: [0.; TAPS],
+      downsample_index: 0,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.upsample_history = [0.; TAPS];
+    self.upsample_index = 0;
+    self.downsample_history = [0.; TAPS];
+    self.downsample_index = 0;
+  }
+
+  /// Inserts a zero after the input and filters out the resulting image. The input is doubled to
+  /// make up for the energy of the zero.
+  pub fn upsample(&mut self, input: f32) -> [f32; 2] {
+    [input * 2., 0.].map(|x| {
+      Self::write(&mut self.upsample_history, &mut self.upsample_index, x);
+      self.convolve(&self.upsample_history, self.upsample_index)
+    })
+  }
+
+  /// Filters out everything above the new Nyquist frequency and keeps the first of both samples.
+  pub fn downsample(&mut self, input: [f32; 2]) -> f32 {
+    Self::write(
+      &mut self.downsample_history,
+      &mut self.downsample_index,
+      input[0],
+    );
+    let output = self.convolve(&self.downsample_history, self.downsample_index);
+    Self::write(
+      &mut self.downsample_history,
+      &mut self.downsample_index,
+      input[1],
+    );
+    output
+  }
+
+  fn write(history: &mut [f32; TAPS], index: &mut usize, input: f32) {
+    history[*index] = input;
+    *index = (*index + 1) % TAPS;
+  }
+
+  fn convolve(&self, history: &[f32; TAPS], index: usize) -> f32 {
+    // every second tap of a halfband filter is zero, except for the center tap
+    (0..TAPS)
+      .filter(|tap| (tap + TAPS / 2) % 2 == 1 || *tap == TAPS / 2)
+      .fold(0., |result, tap| {
+        result + history[(index + tap) % TAPS] * self.coefficients[tap]
+      })
+  }
+
+  fn get_coefficients() -> [f32; TAPS] {
+    let half_taps = (TAPS / 2) as f64;
+    let mut coefficients = [0.; TAPS];
+    for (i, coefficient) in coefficients.iter_mut().enumerate() {
+      let x = i as f64 - half_taps;
+      let sinc = if x == 0. {
+        1.
+      } else {
+        (x * 0.5 * std::f64::consts::PI).sin() / (x * 0.5 * std::f64::consts::PI)
+      };
+      let phase = std::f64::consts::PI * x / (half_taps + 1.);
+      let window = 0.42 + 0.5 * phase.cos() + 0.08 * (2. * phase).cos();
+      *coefficient = (sinc * window) as f32;
+    }
+    let sum: f32 = coefficients.iter().sum();
+    coefficients.map(|coefficient| coefficient / sum)
+  }
+}
@@ -0,0 +1,30 @@
+/// Delays the input frames by a whole number of samples, up to `LENGTH - 1`.
+pub struct LatencyCompensation<const LENGTH: usize> {
+  buffer: [((f32, f32), (f32, f32)); LENGTH],
+  write_pointer: usize,
+}
+
+impl<const LENGTH: usize> LatencyCompensation<LENGTH> {
+  pub fn new() -> Self {
+    Self {
+      buffer: [((0., 0.), (0., 0.)); LENGTH],
+      write_pointer: 0,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.buffer = [((0., 0.), (0., 0.)); LENGTH];
+    self.write_pointer = 0;
+  }
+
+  pub fn process(
+    &mut self,
+    input: ((f32, f32), (f32, f32)),
+    latency: usize,
+  ) -> ((f32, f32), (f32, f32)) {
+    self.buffer[self.write_pointer] = input;
+    let output = self.buffer[(self.write_pointer + LENGTH - latency) % LENGTH];
+    self.write_pointer = (self.write_pointer + 1) % LENGTH;
+    output
+  }
+}
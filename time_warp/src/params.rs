@@ -1,10 +1,19 @@
+mod file_window;
 mod phasor;
+mod settings;
 mod smooth;
 mod stopwatch;
-use crate::{MAX_DELAY_TIME, MIN_DELAY_TIME};
+use crate::{
+  delay_line::Interpolation, oversampler::Oversampling, saturation::Saturation, MIN_DELAY_TIME,
+};
+pub use settings::{
+  DuckingSettings, EnvelopeSettings, EqSettings, FilterSettings, QualitySettings, RecordSettings,
+  ReverbSettings, SaturationSettings, Settings, ShimmerSettings, TapeSettings, WowFlutterSettings,
+};
 pub use smooth::Smoother;
 use {
   crate::shared::float_ext::FloatExt,
+  file_window::FileWindow,
   phasor::Phasor,
   smooth::{CascadedExponentialSmooth, LinearSmooth},
   stopwatch::Stopwatch,
@@ -17,6 +26,56 @@ pub enum SampleMode {
   Sampler,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum InputSource {
+  Main,
+  Sidechain,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TapeSpeed {
+  Half,
+  Normal,
+  Double,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ShimmerInterval {
+  OctaveDown,
+  Fifth,
+  OctaveUp,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FilterPlacement {
+  Off,
+  Feedback,
+  Grains,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FilterMode {
+  Lowpass,
+  Highpass,
+  Bandpass,
+  Notch,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FilterSlope {
+  Db12,
+  Db24,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EnvelopeDestination {
+  Density,
+  Size,
+  Wet,
+  RecordingGain,
+  FilterCutoff,
+}
+
 pub struct Params {
   pub scan: f32,
   pub spray: f32,
@@ -25,14 +84,46 @@ pub struct Params {
   pub stereo: f32,
   pub speed: f32,
   pub stretch: f32,
+  pub tape_speed: f32,
   pub recording_gain: LinearSmooth,
   pub playback_gain: LinearSmooth,
   pub time: CascadedExponentialSmooth,
   pub filter_coefficients: ([f32; 3], [f32; 3]),
   pub recycle: LinearSmooth,
   pub feedback: LinearSmooth,
+  pub shimmer: LinearSmooth,
+  pub shimmer_speed: f64,
+  pub saturation: Saturation,
+  pub drive: LinearSmooth,
+  pub wow: LinearSmooth,
+  pub flutter: LinearSmooth,
+  pub filter_placement: FilterPlacement,
+  pub filter_mode: FilterMode,
+  pub filter_slope: FilterSlope,
+  pub filter_cutoff: LinearSmooth,
+  pub filter_resonance: LinearSmooth,
+  pub reverb_size: LinearSmooth,
+  pub reverb_decay: f32,
+  pub reverb_mix: LinearSmooth,
+  pub reverb_to_delay: bool,
+  pub eq_low: LinearSmooth,
+  pub eq_mid: LinearSmooth,
+  pub eq_mid_freq: f32,
+  pub eq_high: LinearSmooth,
   pub dry: LinearSmooth,
   pub wet: LinearSmooth,
+  pub envelope_attack: f32,
+  pub envelope_release: f32,
+  pub envelope_amount: f32,
+  pub envelope_destination: EnvelopeDestination,
+  pub gate_threshold: f32,
+  pub record_source: InputSource,
+  pub envelope_source: InputSource,
+  pub interpolation: Interpolation,
+  pub oversampling: Oversampling,
+  pub duck_threshold: f32,
+  pub duck_amount: f32,
+  pub duck_release: f32,
   pub midi_enabled: bool,
   pub sync_position: bool,
   is_initialized: bool,
@@ -45,6 +136,8 @@ pub struct Params {
   pub should_reset_playback: bool,
   pub sample_mode: Option<SampleMode>,
   file_duration: Option<f32>,
+  file_window: Option<FileWindow>,
+  file_window_request: Option<f32>,
   loop_duration: Option<f32>,
   is_recording_in_delay_mode: bool,
   stopwatch: Stopwatch,
@@ -55,10 +148,17 @@ pub struct Params {
   prev_midi_enabled: bool,
   pitch_bend_factor: f32,
   start_offset_phasor: Phasor,
+  record_threshold: f32,
+  is_armed: bool,
+  prev_record: bool,
+  buffer_size: usize,
+  block_sample_index: usize,
+  length: f32,
+  max_delay_time: f32,
 }
 
 impl Params {
-  pub fn new(sample_rate: f32) -> Self {
+  pub fn new(sample_rate: f32, max_delay_time: f32) -> Self {
     Self {
       scan: 0.,
       spray: 0.,
@@ -67,14 +167,46 @@ impl Params {
       stereo: 1.,
       speed: 1.,
       stretch: 0.,
+      tape_speed: 1.,
       recording_gain: LinearSmooth::new(sample_rate, 55.),
       playback_gain: LinearSmooth::new(sample_rate, 55.),
       time: CascadedExponentialSmooth::new(sample_rate, 1.29),
       filter_coefficients: ([0.; 3], [0.; 3]),
       recycle: LinearSmooth::new(sample_rate, 20.),
       feedback: LinearSmooth::new(sample_rate, 20.),
+      shimmer: LinearSmooth::new(sample_rate, 20.),
+      shimmer_speed: 2.,
+      saturation: Saturation::Clip,
+      drive: LinearSmooth::new(sample_rate, 20.),
+      wow: LinearSmooth::new(sample_rate, 20.),
+      flutter: LinearSmooth::new(sample_rate, 20.),
+      filter_placement: FilterPlacement::Off,
+      filter_mode: FilterMode::Lowpass,
+      filter_slope: FilterSlope::Db12,
+      filter_cutoff: LinearSmooth::new(sample_rate, 20.),
+      filter_resonance: LinearSmooth::new(sample_rate, 20.),
+      reverb_size: LinearSmooth::new(sample_rate, 5.),
+      reverb_decay: 2.,
+      reverb_mix: LinearSmooth::new(sample_rate, 20.),
+      reverb_to_delay: false,
+      eq_low: LinearSmooth::new(sample_rate, 20.),
+      eq_mid: LinearSmooth::new(sample_rate, 20.),
+      eq_mid_freq: 1000.,
+      eq_high: LinearSmooth::new(sample_rate, 20.),
       dry: LinearSmooth::new(sample_rate, 20.),
       wet: LinearSmooth::new(sample_rate, 20.),
+      envelope_attack: 10.,
+      envelope_release: 250.,
+      envelope_amount: 0.,
+      envelope_destination: EnvelopeDestination::Density,
+      gate_threshold: 0.,
+      record_source: InputSource::Main,
+      envelope_source: InputSource::Main,
+      interpolation: Interpolation::Linear,
+      oversampling: Oversampling::Off,
+      duck_threshold: 0.,
+      duck_amount: 0.,
+      duck_release: 250.,
       midi_enabled: false,
       sync_position: true,
       is_initialized: false,
@@ -87,6 +219,8 @@ impl Params {
       should_reset_playback: false,
       sample_mode: None,
       file_duration: None,
+      file_window: None,
+      file_window_request: None,
       loop_duration: None,
       is_recording_in_delay_mode: false,
       stopwatch: Stopwatch::new(sample_rate),
@@ -97,14 +231,24 @@ impl Params {
       prev_midi_enabled: false,
       pitch_bend_factor: 1.,
       start_offset_phasor: Phasor::new(sample_rate),
+      record_threshold: 0.,
+      is_armed: false,
+      prev_record: false,
+      buffer_size: 0,
+      block_sample_index: 0,
+      length: 1.,
+      max_delay_time,
     }
   }
 
   pub fn reset(&mut self) {
     self.is_initialized = false;
+    self.is_armed = false;
     self.reset_playback();
   }
 
+  /// Sets the parameter values for the next `buffer_size` samples. Wrappers can call this at every
+  /// sample offset where a parameter changes, which keeps loop boundaries sample accurate.
   pub fn set(
     &mut self,
     record: bool,
@@ -132,9 +276,30 @@ impl Params {
     sync_position: bool,
     dry: f32,
     wet: f32,
+    settings: &Settings,
     buffer_size: usize,
   ) {
-    self.scan = scan;
+    let Settings {
+      record: record_settings,
+      tape,
+      shimmer,
+      saturation,
+      wow_flutter,
+      filter,
+      reverb,
+      eq,
+      envelope,
+      ducking,
+      quality,
+    } = settings;
+    self.file_window_request = self
+      .file_window
+      .as_mut()
+      .and_then(|file_window| file_window.request(scan));
+    self.scan = self
+      .file_window
+      .as_ref()
+      .map_or(scan, |file_window| file_window.get_scan(scan));
     self.spray = spray;
     self.size = size;
     self.stereo = stereo;
@@ -148,6 +313,39 @@ impl Params {
     self.prev_midi_enabled = self.midi_enabled;
     self.midi_enabled = midi_enabled;
     self.sync_position = sync_position;
+    self.length = length;
+    self.shimmer_speed = match shimmer.interval {
+      ShimmerInterval::OctaveDown => 0.5,
+      ShimmerInterval::Fifth => 2_f64.powf(7. / 12.),
+      ShimmerInterval::OctaveUp => 2.,
+    };
+    self.saturation = saturation.saturation;
+    self.reverb_decay = reverb.decay;
+    self.reverb_to_delay = reverb.to_delay;
+    self.eq_mid_freq = eq.mid_freq;
+    self.filter_placement = filter.placement;
+    self.filter_mode = filter.mode;
+    self.filter_slope = filter.slope;
+    self.envelope_attack = envelope.attack;
+    self.envelope_release = envelope.release;
+    self.envelope_amount = envelope.amount;
+    self.envelope_destination = envelope.destination;
+    self.gate_threshold = if envelope.gate_threshold <= -70. {
+      0.
+    } else {
+      envelope.gate_threshold.fast_dbtoa()
+    };
+    self.duck_threshold = if ducking.threshold <= -70. {
+      0.
+    } else {
+      ducking.threshold.fast_dbtoa()
+    };
+    self.duck_amount = ducking.amount;
+    self.duck_release = ducking.release;
+    self.record_source = record_settings.source;
+    self.envelope_source = envelope.source;
+    self.interpolation = quality.interpolation;
+    self.oversampling = quality.oversampling;
 
     let sample_mode_has_changed = self
       .sample_mode
@@ -162,16 +360,44 @@ impl Params {
       self.should_reset_playback = true;
       self.prev_file_duration = self.file_duration;
       self.file_duration = None;
+      self.file_window = None;
       self.stopwatch.reset();
       self.loop_duration = None;
       if sample_mode == SampleMode::Looper {
-        self.time.reset(MAX_DELAY_TIME);
+        self.time.reset(self.max_delay_time);
       }
     }
 
+    self.buffer_size = buffer_size;
+    self.block_sample_index = 0;
+    self.record_threshold = if record_settings.threshold <= -70. {
+      0.
+    } else {
+      record_settings.threshold.fast_dbtoa()
+    };
+    let prev_record = self.prev_record;
+    self.prev_record = record;
+    let record = self.arm_record(record, prev_record, sample_mode);
+
+    self.tape_speed = if sample_mode == SampleMode::Looper {
+      let tape_speed = match tape.speed {
+        TapeSpeed::Half => 0.5,
+        TapeSpeed::Normal => 1.,
+        TapeSpeed::Double => 2.,
+      };
+      if tape.reverse {
+        -tape_speed
+      } else {
+        tape_speed
+      }
+    } else {
+      1.
+    };
+
     let overridden_play = self.override_play(play, &sample_mode);
     let recording_gain = if record { 1. } else { 0. };
     let playback_gain = if overridden_play { 1. } else { 0. };
+    let drive = saturation.drive.fast_dbtoa();
     let dry = if dry <= -70. { 0. } else { dry.fast_dbtoa() };
     let wet = if wet <= -70. { 0. } else { wet.fast_dbtoa() };
 
@@ -182,6 +408,17 @@ impl Params {
       self.set_time(sample_mode, record, play, time, length, buffer_size);
       self.recycle.set_target(recycle);
       self.feedback.set_target(feedback);
+      self.shimmer.set_target(shimmer.amount);
+      self.drive.set_target(drive);
+      self.wow.set_target(wow_flutter.wow);
+      self.flutter.set_target(wow_flutter.flutter);
+      self.filter_cutoff.set_target(filter.cutoff);
+      self.filter_resonance.set_target(filter.resonance);
+      self.reverb_size.set_target(reverb.size);
+      self.reverb_mix.set_target(reverb.mix);
+      self.eq_low.set_target(eq.low);
+      self.eq_mid.set_target(eq.mid);
+      self.eq_high.set_target(eq.high);
       self.dry.set_target(dry);
       self.wet.set_target(wet);
       self.attack.set_target(attack);
@@ -195,6 +432,17 @@ impl Params {
       self.reset_time(time, length);
       self.recycle.reset(recycle);
       self.feedback.reset(feedback);
+      self.shimmer.reset(shimmer.amount);
+      self.drive.reset(drive);
+      self.wow.reset(wow_flutter.wow);
+      self.flutter.reset(wow_flutter.flutter);
+      self.filter_cutoff.reset(filter.cutoff);
+      self.filter_resonance.reset(filter.resonance);
+      self.reverb_size.reset(reverb.size);
+      self.reverb_mix.reset(reverb.mix);
+      self.eq_low.reset(eq.low);
+      self.eq_mid.reset(eq.mid);
+      self.eq_high.reset(eq.high);
       self.dry.reset(dry);
       self.wet.reset(wet);
       self.attack.reset(attack);
@@ -217,6 +465,8 @@ impl Params {
   }
 
   pub fn settle(&mut self) {
+    self.block_sample_index += 1;
+
     if self.should_reset_playback {
       self.should_reset_playback = false;
     }
@@ -226,8 +476,78 @@ impl Params {
     }
   }
 
+  /// Starts an armed recording at the current sample once the input exceeds the record threshold.
+  pub fn process_record_threshold(&mut self, input: (f32, f32)) {
+    if !self.is_armed || input.0.abs().max(input.1.abs()) < self.record_threshold {
+      return;
+    }
+    self.is_armed = false;
+    self.recording_gain.reset(1.);
+    if self.sample_mode == Some(SampleMode::Looper) {
+      self.stopwatch.process(
+        true,
+        self.buffer_size.saturating_sub(self.block_sample_index),
+      );
+    }
+  }
+
+  /// Returns the time of the recorded loop in Looper mode.
+  pub fn get_loop_time(&self) -> Option<f32> {
+    match (self.sample_mode, self.loop_duration) {
+      (Some(SampleMode::Looper), Some(_)) => Some(self.time.get_target()),
+      _ => None,
+    }
+  }
+
+  pub fn scale_loop_duration(&mut self, factor: f32) {
+    if let Some(loop_duration) = self.loop_duration {
+      let loop_duration = loop_duration * factor;
+      self.loop_duration = Some(loop_duration);
+      self
+        .time
+        .reset(self.multiply_by_length(loop_duration, self.length));
+      self.should_reset_playback = true;
+    }
+  }
+
+  pub fn is_armed(&self) -> bool {
+    self.is_armed
+  }
+
   pub fn set_file_duration(&mut self, file_duration: f32) {
     self.file_duration = Some(file_duration);
+    self.file_window = None;
+  }
+
+  /// Sets the part of a streamed file that's in the delay line, after `set_file_duration` is
+  /// called with the duration of that part.
+  pub fn set_file_window(&mut self, start: f32, file_duration: f32) {
+    if let Some(duration) = self.file_duration {
+      self.file_window = Some(FileWindow::new(start, duration, file_duration));
+    }
+  }
+
+  /// Returns the scan position to read the next part of a streamed file around.
+  pub fn get_file_window_request(&self) -> Option<f32> {
+    self.file_window_request
+  }
+
+  /// Lets a streamed file request its window again, because the requested one couldn't be read.
+  pub fn cancel_file_window_request(&mut self) {
+    if let Some(file_window) = &mut self.file_window {
+      file_window.cancel_request();
+    }
+  }
+
+  /// Forgets the recorded loop or file, because a delay line of the new size starts out empty.
+  pub fn set_max_delay_time(&mut self, max_delay_time: f32) {
+    self.max_delay_time = max_delay_time;
+    self.prev_file_duration = self.file_duration;
+    self.file_duration = None;
+    self.file_window = None;
+    self.loop_duration = None;
+    self.stopwatch.reset();
+    self.should_reset_playback = true;
   }
 
   pub fn reset_playback(&mut self) {
@@ -251,6 +571,22 @@ impl Params {
     self.time.get_target()
   }
 
+  fn arm_record(&mut self, record: bool, prev_record: bool, sample_mode: SampleMode) -> bool {
+    if !record || self.record_threshold == 0. {
+      self.is_armed = false;
+      return record;
+    }
+    let can_arm = match sample_mode {
+      SampleMode::Delay => true,
+      SampleMode::Looper => self.loop_duration.is_none(),
+      SampleMode::Sampler => false,
+    };
+    if !prev_record && can_arm && self.record_threshold > 0. {
+      self.is_armed = true;
+    }
+    !self.is_armed
+  }
+
   fn override_play(&mut self, play: bool, sample_mode: &SampleMode) -> bool {
     match (play, sample_mode, self.loop_duration, self.file_duration) {
       (true, SampleMode::Looper, None, None) => false,
@@ -284,11 +620,11 @@ impl Params {
         if file_duration == prev_file_duration {
           self
             .time
-            .set_target(Self::multiply_by_length(file_duration, length));
+            .set_target(self.multiply_by_length(file_duration, length));
         } else {
           self
             .time
-            .reset(Self::multiply_by_length(file_duration, length));
+            .reset(self.multiply_by_length(file_duration, length));
           self.should_reset_start_offset = true;
           self.should_reset_playback = true;
         }
@@ -296,7 +632,7 @@ impl Params {
       (Some(file_duration), None, _, _) => {
         self
           .time
-          .reset(Self::multiply_by_length(file_duration, length));
+          .reset(self.multiply_by_length(file_duration, length));
         self.should_reset_start_offset = true;
         self.should_reset_playback = true;
       }
@@ -304,9 +640,11 @@ impl Params {
         // stop stopwatch if play changed from false to true
         let start = record && !(!self.prev_play && play);
         if let Some(loop_duration) = self.stopwatch.process(start, buffer_size) {
+          // a loop that's recorded for longer than the buffer only keeps its last part
+          let loop_duration = loop_duration.min(self.max_delay_time);
           self
             .time
-            .reset(Self::multiply_by_length(loop_duration, length));
+            .reset(self.multiply_by_length(loop_duration, length));
           self.loop_duration = Some(loop_duration);
           self.should_reset_start_offset = true;
           self.should_reset_playback = true;
@@ -315,22 +653,22 @@ impl Params {
       (_, _, Some(loop_duration), SampleMode::Looper) => {
         self
           .time
-          .set_target(Self::multiply_by_length(loop_duration, length));
+          .set_target(self.multiply_by_length(loop_duration, length));
       }
       _ => {
         if record && !self.is_recording_in_delay_mode {
           self.is_recording_in_delay_mode = true;
           self.should_reset_start_offset = true;
         }
-        self.time.set_target(time);
+        self.time.set_target(time.min(self.max_delay_time));
       }
     }
   }
 
   fn reset_time(&mut self, time: f32, length: f32) {
     match (self.file_duration, self.loop_duration) {
-      (Some(dur), None) => self.time.reset(Self::multiply_by_length(dur, length)),
-      (None, Some(dur)) => self.time.reset(Self::multiply_by_length(dur, length)),
+      (Some(dur), None) => self.time.reset(self.multiply_by_length(dur, length)),
+      (None, Some(dur)) => self.time.reset(self.multiply_by_length(dur, length)),
       _ => {
         self.time.reset(time);
       }
@@ -349,7 +687,169 @@ impl Params {
   }
 
   #[inline(always)]
-  fn multiply_by_length(duration: f32, length: f32) -> f32 {
-    (duration * length).max(MIN_DELAY_TIME)
+  fn multiply_by_length(&self, duration: f32, length: f32) -> f32 {
+    (duration * length).clamp(MIN_DELAY_TIME, self.max_delay_time)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    DuckingSettings, EnvelopeDestination, EnvelopeSettings, EqSettings, FilterMode,
+    FilterPlacement, FilterSettings, FilterSlope, InputSource, Params, QualitySettings,
+    RecordSettings, ReverbSettings, SampleMode, SaturationSettings, Settings, ShimmerInterval,
+    ShimmerSettings, TapeSettings, TapeSpeed, WowFlutterSettings,
+  };
+  use crate::{delay_line::Interpolation, oversampler::Oversampling, saturation::Saturation};
+
+  fn set_params(params: &mut Params, record: bool, record_threshold: f32, buffer_size: usize) {
+    params.set(
+      record,
+      true,
+      false,
+      0.,
+      0.,
+      false,
+      1.,
+      1.,
+      1.,
+      0.,
+      0.,
+      0.,
+      SampleMode::Looper,
+      1000.,
+      1.,
+      0.,
+      1.,
+      1.,
+      5.,
+      1.,
+      5.,
+      false,
+      false,
+      0.,
+      0.,
+      &Settings {
+        record: RecordSettings {
+          threshold: record_threshold,
+          source: InputSource::Main,
+        },
+        tape: TapeSettings {
+          speed: TapeSpeed::Normal,
+          reverse: false,
+        },
+        shimmer: ShimmerSettings {
+          amount: 0.,
+          interval: ShimmerInterval::OctaveUp,
+        },
+        saturation: SaturationSettings {
+          saturation: Saturation::Clip,
+          drive: 0.,
+        },
+        wow_flutter: WowFlutterSettings {
+          wow: 0.,
+          flutter: 0.,
+        },
+        filter: FilterSettings {
+          placement: FilterPlacement::Off,
+          mode: FilterMode::Lowpass,
+          slope: FilterSlope::Db12,
+          cutoff: 1000.,
+          resonance: 0.,
+        },
+        reverb: ReverbSettings {
+          size: 0.5,
+          decay: 2.,
+          mix: 0.,
+          to_delay: false,
+        },
+        eq: EqSettings {
+          low: 0.,
+          mid: 0.,
+          mid_freq: 1000.,
+          high: 0.,
+        },
+        envelope: EnvelopeSettings {
+          attack: 10.,
+          release: 250.,
+          amount: 0.,
+          destination: EnvelopeDestination::Density,
+          gate_threshold: -70.,
+          source: InputSource::Main,
+        },
+        ducking: DuckingSettings {
+          threshold: -70.,
+          amount: 0.,
+          release: 250.,
+        },
+        quality: QualitySettings {
+          interpolation: Interpolation::Linear,
+          oversampling: Oversampling::Off,
+        },
+      },
+      buffer_size,
+    );
+  }
+
+  fn process_block(params: &mut Params, inputs: &[f32]) {
+    for input in inputs {
+      params.process_record_threshold((*input, *input));
+      params.settle();
+    }
+  }
+
+  #[test]
+  fn should_measure_loop_duration_across_split_blocks() {
+    let mut params = Params::new(1000., 60000.);
+    set_params(&mut params, false, -70., 3);
+    process_block(&mut params, &[0.; 3]);
+    set_params(&mut params, true, -70., 5);
+    process_block(&mut params, &[0.; 5]);
+    set_params(&mut params, true, -70., 2);
+    process_block(&mut params, &[0.; 2]);
+    set_params(&mut params, false, -70., 6);
+    assert_eq!(params.loop_duration, Some(7.));
+  }
+
+  #[test]
+  fn should_limit_loop_time_to_max_delay_time() {
+    let mut params = Params::new(1000., 12.);
+    set_params(&mut params, false, -70., 3);
+    set_params(&mut params, true, -70., 20);
+    process_block(&mut params, &[0.; 20]);
+    set_params(&mut params, false, -70., 3);
+    assert_eq!(params.loop_duration, Some(12.));
+    assert_eq!(params.get_target_time(), 12.);
+  }
+
+  #[test]
+  fn should_arm_recording_when_record_threshold_is_set() {
+    let mut params = Params::new(1000., 60000.);
+    set_params(&mut params, false, -20., 10);
+    set_params(&mut params, true, -20., 10);
+    assert!(params.is_armed());
+    process_block(&mut params, &[0.; 10]);
+    assert!(params.is_armed());
+  }
+
+  #[test]
+  fn should_not_arm_recording_without_record_threshold() {
+    let mut params = Params::new(1000., 60000.);
+    set_params(&mut params, false, -70., 10);
+    set_params(&mut params, true, -70., 10);
+    assert!(!params.is_armed());
+  }
+
+  #[test]
+  fn should_start_loop_at_the_sample_that_exceeds_the_record_threshold() {
+    let mut params = Params::new(1000., 60000.);
+    set_params(&mut params, false, -20., 10);
+    set_params(&mut params, true, -20., 10);
+    process_block(&mut params, &[0., 0., 0., 0., 0.5, 0., 0., 0., 0., 0.]);
+    assert!(!params.is_armed());
+    set_params(&mut params, true, -20., 10);
+    process_block(&mut params, &[0.; 10]);
+    set_params(&mut params, false, -20., 10);
+    assert_eq!(params.loop_duration, Some(16.));
   }
 }
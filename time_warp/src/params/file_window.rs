@@ -0,0 +1,75 @@
+/// The part of a streamed file that's in the delay line.
+pub struct FileWindow {
+  start: f32,
+  duration: f32,
+  file_duration: f32,
+  is_requested: bool,
+}
+
+impl FileWindow {
+  pub fn new(start: f32, duration: f32, file_duration: f32) -> Self {
+    Self {
+      start,
+      duration,
+      file_duration,
+      is_requested: false,
+    }
+  }
+
+  /// Maps a scan position relative to the file to one relative to the window.
+  pub fn get_scan(&self, scan: f32) -> f32 {
+    ((scan * self.file_duration - self.start) / self.duration).clamp(0., 1.)
+  }
+
+  /// Returns the scan position to read a new window around, once the scan position gets close to
+  /// the edges of the window. It's returned once, until the next window is set.
+  pub fn request(&mut self, scan: f32) -> Option<f32> {
+    let window_scan = self.get_scan(scan);
+    let is_at_file_edge = (window_scan < 0.25 && self.start <= 0.)
+      || (window_scan > 0.75 && self.start + self.duration >= self.file_duration);
+    if self.is_requested || (0.25..=0.75).contains(&window_scan) || is_at_file_edge {
+      None
+    } else {
+      self.is_requested = true;
+      Some(scan)
+    }
+  }
+
+  /// Lets the window be requested again, after reading the requested one failed.
+  pub fn cancel_request(&mut self) {
+    self.is_requested = false;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::FileWindow;
+  use crate::assert_approximately_eq;
+
+  #[test]
+  fn should_map_scan_to_window() {
+    let window = FileWindow::new(1000., 2000., 10000.);
+    assert_approximately_eq!(window.get_scan(0.1), 0., 4);
+    assert_approximately_eq!(window.get_scan(0.2), 0.5, 4);
+    assert_approximately_eq!(window.get_scan(0.3), 1., 4);
+    assert_approximately_eq!(window.get_scan(0.9), 1., 4);
+  }
+
+  #[test]
+  fn should_request_window_once_near_its_edges() {
+    let mut window = FileWindow::new(1000., 2000., 10000.);
+    assert_eq!(window.request(0.2), None);
+    assert_eq!(window.request(0.28), Some(0.28));
+    assert_eq!(window.request(0.29), None);
+    window.cancel_request();
+    assert_eq!(window.request(0.29), Some(0.29));
+  }
+
+  #[test]
+  fn should_not_request_window_beyond_the_file() {
+    let mut window = FileWindow::new(0., 2000., 10000.);
+    assert_eq!(window.request(0.), None);
+    let mut window = FileWindow::new(8000., 2000., 10000.);
+    assert_eq!(window.request(1.), None);
+  }
+}
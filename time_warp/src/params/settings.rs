@@ -0,0 +1,103 @@
+use super::{
+  EnvelopeDestination, FilterMode, FilterPlacement, FilterSlope, InputSource, ShimmerInterval,
+  TapeSpeed,
+};
+use crate::{delay_line::Interpolation, oversampler::Oversampling, saturation::Saturation};
+
+/// The settings of every section, which `Params::set` takes at once.
+#[derive(Clone, Copy)]
+pub struct Settings {
+  pub record: RecordSettings,
+  pub tape: TapeSettings,
+  pub shimmer: ShimmerSettings,
+  pub saturation: SaturationSettings,
+  pub wow_flutter: WowFlutterSettings,
+  pub filter: FilterSettings,
+  pub reverb: ReverbSettings,
+  pub eq: EqSettings,
+  pub envelope: EnvelopeSettings,
+  pub ducking: DuckingSettings,
+  pub quality: QualitySettings,
+}
+
+#[derive(Clone, Copy)]
+pub struct RecordSettings {
+  /// Recording starts once the input exceeds this level in dB, -70 dB disables the threshold.
+  pub threshold: f32,
+  pub source: InputSource,
+}
+
+#[derive(Clone, Copy)]
+pub struct TapeSettings {
+  pub speed: TapeSpeed,
+  pub reverse: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct ShimmerSettings {
+  pub amount: f32,
+  pub interval: ShimmerInterval,
+}
+
+#[derive(Clone, Copy)]
+pub struct SaturationSettings {
+  pub saturation: Saturation,
+  /// The drive in dB.
+  pub drive: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct WowFlutterSettings {
+  pub wow: f32,
+  pub flutter: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct FilterSettings {
+  pub placement: FilterPlacement,
+  pub mode: FilterMode,
+  pub slope: FilterSlope,
+  pub cutoff: f32,
+  pub resonance: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct ReverbSettings {
+  pub size: f32,
+  pub decay: f32,
+  pub mix: f32,
+  pub to_delay: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct EqSettings {
+  pub low: f32,
+  pub mid: f32,
+  pub mid_freq: f32,
+  pub high: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct EnvelopeSettings {
+  pub attack: f32,
+  pub release: f32,
+  pub amount: f32,
+  pub destination: EnvelopeDestination,
+  /// The gate threshold in dB, -70 dB disables the gate.
+  pub gate_threshold: f32,
+  pub source: InputSource,
+}
+
+#[derive(Clone, Copy)]
+pub struct DuckingSettings {
+  /// The threshold in dB, -70 dB disables ducking.
+  pub threshold: f32,
+  pub amount: f32,
+  pub release: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct QualitySettings {
+  pub interpolation: Interpolation,
+  pub oversampling: Oversampling,
+}
@@ -30,6 +30,17 @@ impl LinearSmooth {
       step_counter: 0,
     }
   }
+
+  /// Moves the smoother `steps` samples ahead at once, for values that are read once per block.
+  pub fn advance(&mut self, steps: usize) -> f32 {
+    let ramp_steps = steps.min(self.step_counter);
+    self.current += self.step_size * ramp_steps as f32;
+    self.step_counter -= ramp_steps;
+    if steps > ramp_steps {
+      self.current = self.target;
+    }
+    self.current
+  }
 }
 
 impl Smoother for LinearSmooth {
@@ -235,4 +246,13 @@ mod tests {
     assert_eq!(linear_smooth.next(), 2.9802322e-8);
     assert_eq!(linear_smooth.next(), 0.0);
   }
+
+  #[test]
+  fn should_advance_multiple_steps() {
+    let mut linear_smooth = LinearSmooth::new(5., 1.);
+    linear_smooth.set_target(1.);
+    assert_eq!(linear_smooth.advance(2), 0.4);
+    assert_eq!(linear_smooth.advance(1), 0.6);
+    assert_eq!(linear_smooth.advance(4), 1.0);
+  }
 }
@@ -0,0 +1,158 @@
+use crate::{
+  delay_line::{DelayLine, Interpolation},
+  shared::float_ext::FloatExt,
+};
+
+const DELAY_TIMES: [f32; 4] = [31.7, 37.3, 43.1, 53.9];
+const MIN_SIZE_FACTOR: f32 = 0.25;
+const MAX_SIZE_FACTOR: f32 = 2.;
+const DAMPING: f32 = 0.2;
+
+/// Feedback delay network with four delay lines mixed through a hadamard matrix.
+pub struct Reverb {
+  delay_lines: [DelayLine; 4],
+  damping: [f32; 4],
+  prev_size: f32,
+  prev_decay: f32,
+  delay_times: [f32; 4],
+  gains: [f32; 4],
+  is_bypassed: bool,
+}
+
+impl Reverb {
+  pub fn new(sample_rate: f32) -> Self {
+    let length = (DELAY_TIMES[3] * MAX_SIZE_FACTOR).mstosamps(sample_rate) as usize + 2;
+    Self {
+      delay_lines: [
+        DelayLine::new(length, sample_rate),
+        DelayLine::new(length, sample_rate),
+        DelayLine::new(length, sample_rate),
+        DelayLine::new(length, sample_rate),
+      ],
+      damping: [0.; 4],
+      prev_size: -1.,
+      prev_decay: -1.,
+      delay_times: DELAY_TIMES,
+      gains: [0.; 4],
+      is_bypassed: false,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self
+      .delay_lines
+      .iter_mut()
+      .for_each(|delay_line| delay_line.reset());
+    self.damping = [0.; 4];
+  }
+
+  /// Size ranges from 0 to 1, decay is the RT60 time in seconds.
+  pub fn process(&mut self, input: (f32, f32), size: f32, decay: f32, mix: f32) -> (f32, f32) {
+    if mix == 0. {
+      // clear the network once it's bypassed, so an old tail doesn't play when the mix is raised
+      if !self.is_bypassed {
+        self.reset();
+        self.is_bypassed = true;
+      }
+      return input;
+    }
+    self.is_bypassed = false;
+    self.set_delay_times_and_gains(size, decay);
+
+    let mut outs = [0.; 4];
+    for (i, out) in outs.iter_mut().enumerate() {
+      let delay_out = self.delay_lines[i].read(self.delay_times[i], Interpolation::Linear);
+      self.damping[i] = delay_out + (self.damping[i] - delay_out) * DAMPING;
+      *out = self.damping[i];
+    }
+
+    let [a, b, c, d] = outs;
+    let feedback = [
+      (a + b + c + d) * 0.5,
+      (a - b + c - d) * 0.5,
+      (a + b - c - d) * 0.5,
+      (a - b - c + d) * 0.5,
+    ];
+    let inputs = [input.0, input.1, input.0, input.1];
+    for ((delay_line, gain), (input, feedback)) in self
+      .delay_lines
+      .iter_mut()
+      .zip(self.gains)
+      .zip(inputs.into_iter().zip(feedback))
+    {
+      delay_line.write(input + feedback * gain);
+    }
+
+    let reverb_out = ((a + c) * 0.5, (b + d) * 0.5);
+    (
+      input.0.mix(reverb_out.0, mix),
+      input.1.mix(reverb_out.1, mix),
+    )
+  }
+
+  fn set_delay_times_and_gains(&mut self, size: f32, decay: f32) {
+    if size == self.prev_size && decay == self.prev_decay {
+      return;
+    }
+    let size_factor = MIN_SIZE_FACTOR + size * (MAX_SIZE_FACTOR - MIN_SIZE_FACTOR);
+    for ((delay_time, gain), base_delay_time) in self
+      .delay_times
+      .iter_mut()
+      .zip(self.gains.iter_mut())
+      .zip(DELAY_TIMES)
+    {
+      *delay_time = base_delay_time * size_factor;
+      // the gain per pass through a delay line for a 60 dB decay over the decay time
+      *gain = (-60. * *delay_time / (decay * 1000.)).dbtoa();
+    }
+    self.prev_size = size;
+    self.prev_decay = decay;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Reverb;
+
+  #[test]
+  fn should_pass_input_without_mix() {
+    let mut reverb = Reverb::new(44100.);
+    assert_eq!(reverb.process((0.5, -0.5), 0.5, 2., 0.), (0.5, -0.5));
+  }
+
+  #[test]
+  fn should_produce_a_decaying_tail() {
+    let mut reverb = Reverb::new(44100.);
+    let get_energy = |reverb: &mut Reverb, length: usize| -> f32 {
+      (0..length)
+        .map(|_| {
+          let (left, right) = reverb.process((0., 0.), 0.5, 1., 1.);
+          left * left + right * right
+        })
+        .sum()
+    };
+    reverb.process((1., 1.), 0.5, 1., 1.);
+    let early_energy = get_energy(&mut reverb, 22050);
+    let late_energy = get_energy(&mut reverb, 22050);
+    assert!(early_energy > 0.);
+    assert!(late_energy > 0. && late_energy < early_energy * 0.01);
+  }
+
+  #[test]
+  fn should_not_replay_the_tail_after_bypass() {
+    let mut reverb = Reverb::new(44100.);
+    reverb.process((1., 1.), 0.5, 10., 1.);
+    for _ in 0..100 {
+      reverb.process((0., 0.), 0.5, 10., 1.);
+    }
+    reverb.process((0., 0.), 0.5, 10., 0.);
+
+    let energy: f32 = (0..22050)
+      .map(|_| {
+        let (left, right) = reverb.process((0., 0.), 0.5, 10., 1.);
+        left * left + right * right
+      })
+      .sum();
+    assert_eq!(energy, 0.);
+  }
+}
@@ -0,0 +1,83 @@
+use std::f32::consts::{FRAC_2_PI, FRAC_PI_2};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Saturation {
+  Clip,
+  SoftClip,
+  Tape,
+  Tube,
+}
+
+impl Saturation {
+  /// Saturates the input. The makeup gain is unity at 0 dB drive, and keeps a full scale input at
+  /// the level it has at 0 dB drive as the drive goes up.
+  pub fn process(&self, input: f32, drive: f32) -> f32 {
+    let x = input * drive;
+    let y = match self {
+      Saturation::Clip => x.clamp(-1., 1.),
+      Saturation::SoftClip => x.tanh(),
+      Saturation::Tape => (x * FRAC_PI_2).atan() * FRAC_2_PI,
+      Saturation::Tube => {
+        // asymmetric curve which adds even harmonics
+        if x >= 0. {
+          1. - (-x).exp()
+        } else {
+          x / (1. - x)
+        }
+      }
+    };
+    y * (1_f32.tanh() / drive.tanh())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Saturation;
+  use crate::assert_approximately_eq;
+
+  #[test]
+  fn should_keep_small_signals_unaltered() {
+    for saturation in [
+      Saturation::Clip,
+      Saturation::SoftClip,
+      Saturation::Tape,
+      Saturation::Tube,
+    ] {
+      assert_approximately_eq!(saturation.process(0.001, 1.), 0.001, 5);
+      assert_approximately_eq!(saturation.process(-0.001, 1.), -0.001, 5);
+    }
+  }
+
+  #[test]
+  fn should_limit_large_signals() {
+    for saturation in [
+      Saturation::Clip,
+      Saturation::SoftClip,
+      Saturation::Tape,
+      Saturation::Tube,
+    ] {
+      assert!(saturation.process(100., 1.).abs() <= 1.);
+      assert!(saturation.process(-100., 1.).abs() <= 1.);
+    }
+  }
+
+  #[test]
+  fn should_clamp_at_unity_drive() {
+    for x in [-2., -1., -0.5, 0., 0.5, 1., 2.] {
+      assert_eq!(Saturation::Clip.process(x, 1.), x.clamp(-1., 1.));
+    }
+  }
+
+  #[test]
+  fn should_keep_the_ceiling_at_high_drive() {
+    for saturation in [
+      Saturation::Clip,
+      Saturation::SoftClip,
+      Saturation::Tape,
+      Saturation::Tube,
+    ] {
+      assert_approximately_eq!(saturation.process(100., 16.), 1_f32.tanh(), 2);
+      assert_approximately_eq!(saturation.process(-100., 16.), -1_f32.tanh(), 2);
+    }
+  }
+}
@@ -0,0 +1,151 @@
+use crate::{shared::float_ext::FloatExt, MIN_DELAY_TIME};
+
+/// Changes the speed and direction of a recorded loop the way a tape would.
+/// On a speed change the loop is rewritten once through the delay line at the relative speed,
+/// after which the loop is played back and overdubbed at its new length.
+pub struct Tape {
+  sample_rate: f32,
+  max_delay_time: f32,
+  speed: f32,
+  target_speed: f32,
+  relative_speed: f32,
+  base_time: f32,
+  index: usize,
+  length: usize,
+  is_active: bool,
+}
+
+impl Tape {
+  pub fn new(sample_rate: f32, max_delay_time: f32) -> Self {
+    Self {
+      sample_rate,
+      max_delay_time,
+      speed: 1.,
+      target_speed: 1.,
+      relative_speed: 1.,
+      base_time: 0.,
+      index: 0,
+      length: 0,
+      is_active: false,
+    }
+  }
+
+  pub fn set_max_delay_time(&mut self, max_delay_time: f32) {
+    self.max_delay_time = max_delay_time;
+  }
+
+  pub fn reset(&mut self) {
+    self.is_active = false;
+    self.index = 0;
+  }
+
+  /// Returns the factor to scale the loop duration with once the loop is rewritten at the new speed.
+  pub fn process(&mut self, target_speed: f32, loop_time: Option<f32>) -> Option<f32> {
+    let loop_time = match loop_time {
+      Some(loop_time) => loop_time,
+      None => {
+        // without a recorded loop the new speed becomes the speed the loop is recorded at
+        self.is_active = false;
+        self.speed = target_speed;
+        return None;
+      }
+    };
+
+    if self.is_active {
+      self.index += 1;
+      if self.index < self.length {
+        return None;
+      }
+      self.is_active = false;
+      self.speed = self.target_speed;
+      return Some(self.relative_speed.abs().recip());
+    }
+
+    if target_speed != self.speed {
+      self.start(target_speed, loop_time);
+    }
+    None
+  }
+
+  pub fn get_time(&self, time: f32) -> f32 {
+    if !self.is_active {
+      return time;
+    }
+    // Read forwards from one loop ago, or backwards from the write pointer when reversing.
+    let base_time = if self.relative_speed > 0. {
+      self.base_time
+    } else {
+      1.
+    };
+    (base_time + self.index as f32 * (1. - self.relative_speed)).sampstoms(self.sample_rate)
+  }
+
+  fn start(&mut self, target_speed: f32, loop_time: f32) {
+    let relative_speed = target_speed / self.speed;
+    let new_loop_time = loop_time / relative_speed.abs();
+    if !(MIN_DELAY_TIME..=self.max_delay_time).contains(&new_loop_time) {
+      return;
+    }
+    self.target_speed = target_speed;
+    self.relative_speed = relative_speed;
+    self.base_time = loop_time.mstosamps(self.sample_rate);
+    self.index = 0;
+    self.length = new_loop_time.mstosamps(self.sample_rate) as usize;
+    self.is_active = true;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Tape;
+  use crate::assert_approximately_eq;
+
+  #[test]
+  fn should_mirror_the_read_time_when_reversing() {
+    let mut tape = Tape::new(1000., 60000.);
+    assert_eq!(tape.process(-1., Some(100.)), None);
+    assert_approximately_eq!(tape.get_time(100.), 1., 4);
+    assert_eq!(tape.process(-1., Some(100.)), None);
+    assert_approximately_eq!(tape.get_time(100.), 3., 4);
+    for _ in 2..100 {
+      assert_eq!(tape.process(-1., Some(100.)), None);
+    }
+    assert_approximately_eq!(tape.get_time(100.), 199., 4);
+    assert_eq!(tape.process(-1., Some(100.)), Some(1.));
+    assert_approximately_eq!(tape.get_time(100.), 100., 4);
+    assert_eq!(tape.process(-1., Some(100.)), None);
+  }
+
+  #[test]
+  fn should_double_the_loop_length_at_half_speed() {
+    let mut tape = Tape::new(1000., 60000.);
+    tape.process(0.5, Some(100.));
+    assert_approximately_eq!(tape.get_time(100.), 100., 4);
+    for _ in 1..200 {
+      assert_eq!(tape.process(0.5, Some(100.)), None);
+    }
+    assert_approximately_eq!(tape.get_time(100.), 199.5, 4);
+    assert_eq!(tape.process(0.5, Some(100.)), Some(2.));
+  }
+
+  #[test]
+  fn should_not_rewrite_a_loop_that_exceeds_the_max_delay_time() {
+    let mut tape = Tape::new(1000., 150.);
+    assert_eq!(tape.process(0.5, Some(100.)), None);
+    assert_approximately_eq!(tape.get_time(100.), 100., 4);
+    assert_eq!(tape.process(0.5, Some(100.)), None);
+    assert_approximately_eq!(tape.get_time(100.), 100., 4);
+  }
+
+  #[test]
+  fn should_not_rewrite_without_a_recorded_loop() {
+    let mut tape = Tape::new(1000., 60000.);
+    assert_eq!(tape.process(2., None), None);
+    assert_eq!(tape.process(2., Some(100.)), None);
+    assert_approximately_eq!(tape.get_time(100.), 100., 4);
+    tape.process(1., Some(100.));
+    assert_approximately_eq!(tape.get_time(100.), 100., 4);
+    tape.process(1., Some(100.));
+    assert_approximately_eq!(tape.get_time(100.), 100.5, 4);
+  }
+}
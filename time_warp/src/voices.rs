@@ -1,17 +1,19 @@
 mod adsr;
 mod grain_trigger;
 mod grains;
+mod shimmer;
 mod start_position_phasor;
+pub use shimmer::Shimmer;
 use {
   crate::{
-    delay_line::DelayLine,
+    delay_line::{DelayLineReader, Interpolation},
     notes::{ADSRStage, Note},
     shared::float_ext::FloatExt,
     CENTER_GRAIN_DURATION, FADE_TIME, MAX_DENSITY, MAX_VOICE_COUNT, MIN_DELAY_TIME, MIN_DENSITY,
   },
   adsr::ADSR,
   grain_trigger::GrainTrigger,
-  grains::Grains,
+  grains::{GrainParameters, Grains},
   start_position_phasor::StartPositionPhasor,
 };
 
@@ -22,6 +24,27 @@ pub struct Voices {
   grain_triggers: Vec<GrainTrigger>,
   sample_rate: f64,
   has_active_notes: bool,
+  grain_settings: GrainSettings,
+  interpolation: Interpolation,
+}
+
+/// Relative difference below which size, time and density count as unchanged, so ramping values
+/// don't re-derive the grain settings for inaudible changes.
+const GRAIN_SETTINGS_TOLERANCE: f64 = 1e-3;
+
+/// The grain values derived from size, time and density, which only change along with those.
+#[derive(Default)]
+struct GrainSettings {
+  size: f32,
+  time: f32,
+  density: f64,
+  grain_duration: f64,
+  phase_step_size: f64,
+  window_factor: f64,
+  fade_factor: f64,
+  fade_offset: f64,
+  is_in_granular_mode: bool,
+  freq: f64,
 }
 
 impl Voices {
@@ -33,6 +56,8 @@ impl Voices {
       grain_triggers: vec![GrainTrigger::new(sample_rate); MAX_VOICE_COUNT],
       sample_rate: sample_rate as f64,
       has_active_notes: false,
+      grain_settings: GrainSettings::default(),
+      interpolation: Interpolation::Linear,
     }
   }
 
@@ -54,9 +79,8 @@ impl Voices {
 
   pub fn process(
     &mut self,
-    delay_line: &DelayLine,
+    delay_line: &DelayLineReader,
     notes: &mut Vec<Note>,
-    size: f32,
     time: f32,
     density: f64,
     stereo: f32,
@@ -72,18 +96,30 @@ impl Voices {
     release: f32,
     should_reset_playback: bool,
     phase_offset: f32,
+    is_gated: bool,
   ) -> ((f32, f32), f32) {
-    let grain_duration = Self::map_size_to_grain_duration(size, time);
-    let normalized_density = (density - MIN_DENSITY) / (MAX_DENSITY - MIN_DENSITY);
-    let extended_grain_duration = grain_duration + FADE_TIME * (1. - normalized_density);
-    let phase_step_size = extended_grain_duration.mstosamps(self.sample_rate).recip();
-    let min_window_factor = 2.;
-    let max_window_factor = extended_grain_duration / FADE_TIME;
-    let window_factor = max_window_factor.mix(min_window_factor, normalized_density);
-    let fade_factor = time as f64 / FADE_TIME;
-    let fade_offset = fade_factor.recip() + 1.;
-    let is_in_granular_mode = size < 1. || density > 1.;
-    let freq = 1000. / time as f64;
+    let GrainSettings {
+      grain_duration,
+      phase_step_size,
+      window_factor,
+      fade_factor,
+      fade_offset,
+      is_in_granular_mode,
+      freq,
+      ..
+    } = self.grain_settings;
+    let parameters = GrainParameters {
+      scan,
+      spray,
+      stereo,
+      time,
+      is_reversed: stretch < 0.,
+      phase_step_size,
+      window_factor,
+      fade_factor,
+      fade_offset,
+      interpolation: self.interpolation,
+    };
 
     if midi_enabled {
       if sync_position {
@@ -111,21 +147,13 @@ impl Voices {
               }
               let speed = speed * adsr.get_speed();
               let gain = adsr.process(note, attack, decay, sustain, release);
-              let trigger = grain_trigger.process(grain_duration, density, reset);
+              let trigger = grain_trigger.process(grain_duration, density, reset, is_gated);
               let grains_out = grains.process(
                 delay_line,
+                &parameters,
                 trigger,
-                scan,
-                spray,
-                stereo,
-                time,
                 start_position_phase,
-                phase_step_size,
                 speed,
-                stretch < 0.,
-                window_factor,
-                fade_factor,
-                fade_offset,
               );
               (
                 (
@@ -157,21 +185,13 @@ impl Voices {
                 grains.reset();
               }
               let start_position_phase = phasor.process(freq, speed, stretch, is_in_granular_mode);
-              let trigger = grain_trigger.process(grain_duration, density, reset);
+              let trigger = grain_trigger.process(grain_duration, density, reset, is_gated);
               let grains_out = grains.process(
                 delay_line,
+                &parameters,
                 trigger,
-                scan,
-                spray,
-                stereo,
-                time,
                 start_position_phase,
-                phase_step_size,
                 speed,
-                stretch < 0.,
-                window_factor,
-                fade_factor,
-                fade_offset,
               );
               (
                 (
@@ -189,26 +209,60 @@ impl Voices {
         self.grains[0].reset();
       }
       let start_position_phase = self.phasors[0].process(freq, speed, stretch, is_in_granular_mode);
-      let trigger = self.grain_triggers[0].process(grain_duration, density, should_reset_playback);
+      let trigger =
+        self.grain_triggers[0].process(grain_duration, density, should_reset_playback, is_gated);
       let grains_out = self.grains[0].process(
         delay_line,
+        &parameters,
         trigger,
-        scan,
-        spray,
-        stereo,
-        time,
         start_position_phase,
-        phase_step_size,
         speed,
-        stretch < 0.,
-        window_factor,
-        fade_factor,
-        fade_offset,
       );
       ((grains_out.0, grains_out.1), self.grains[0].get_gain())
     }
   }
 
+  /// Derives the grain settings that `process` uses, which is done once per block.
+  pub fn set_grain_settings(
+    &mut self,
+    size: f32,
+    time: f32,
+    density: f64,
+    interpolation: Interpolation,
+  ) {
+    self.interpolation = interpolation;
+    let settings = &mut self.grain_settings;
+    if Self::is_close(size as f64, settings.size as f64)
+      && Self::is_close(time as f64, settings.time as f64)
+      && Self::is_close(density, settings.density)
+    {
+      return;
+    }
+    let grain_duration = Self::map_size_to_grain_duration(size, time);
+    let normalized_density = (density - MIN_DENSITY) / (MAX_DENSITY - MIN_DENSITY);
+    let extended_grain_duration = grain_duration + FADE_TIME * (1. - normalized_density);
+    let min_window_factor = 2.;
+    let max_window_factor = extended_grain_duration / FADE_TIME;
+    let fade_factor = time as f64 / FADE_TIME;
+
+    *settings = GrainSettings {
+      size,
+      time,
+      density,
+      grain_duration,
+      phase_step_size: extended_grain_duration.mstosamps(self.sample_rate).recip(),
+      window_factor: max_window_factor.mix(min_window_factor, normalized_density),
+      fade_factor,
+      fade_offset: fade_factor.recip() + 1.,
+      is_in_granular_mode: size < 1. || density > 1.,
+      freq: 1000. / time as f64,
+    };
+  }
+
+  fn is_close(value: f64, prev_value: f64) -> bool {
+    (value - prev_value).abs() <= prev_value.abs() * GRAIN_SETTINGS_TOLERANCE
+  }
+
   fn map_size_to_grain_duration(size: f32, time: f32) -> f64 {
     if size < 0.5 {
       size * 2. * (CENTER_GRAIN_DURATION - MIN_DELAY_TIME) + MIN_DELAY_TIME
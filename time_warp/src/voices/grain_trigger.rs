@@ -19,13 +19,20 @@ impl GrainTrigger {
     self.delta.reset();
   }
 
-  pub fn process(&mut self, grain_duration: f64, density: f64, reset: bool) -> bool {
+  pub fn process(
+    &mut self,
+    grain_duration: f64,
+    density: f64,
+    reset: bool,
+    is_gated: bool,
+  ) -> bool {
     if reset {
       self.phasor.reset();
       self.delta.reset();
-      return true;
+      return !is_gated;
     }
     let phase = self.phasor.process(1000. / grain_duration * density);
-    self.delta.process(phase) < 0.
+    // keep the phasor running while gated, so triggering resumes in time once the input returns
+    self.delta.process(phase) < 0. && !is_gated
   }
 }
@@ -1,22 +1,40 @@
-mod grain;
-use {crate::delay_line::DelayLine, grain::Grain};
+mod grain_batch;
+use {
+  crate::delay_line::{DelayLineReader, Interpolation},
+  grain_batch::GrainBatch,
+};
+
+/// The values that the grains of a voice are processed with at a sample.
+#[derive(Clone, Copy)]
+pub struct GrainParameters {
+  pub scan: f32,
+  pub spray: f32,
+  pub stereo: f32,
+  pub time: f32,
+  pub is_reversed: bool,
+  pub phase_step_size: f64,
+  pub window_factor: f64,
+  pub fade_factor: f64,
+  pub fade_offset: f64,
+  pub interpolation: Interpolation,
+}
 
 #[derive(Clone)]
 pub struct Grains {
-  grains: [Grain; 12], // extra grains to allow for speed changes without voice stealing
+  batches: [GrainBatch; 3], // 12 grains, extra grains to allow for speed changes without voice stealing
   gain: f32,
 }
 
 impl Grains {
   pub fn new(sample_rate: f32) -> Self {
     Self {
-      grains: [Grain::new(sample_rate); 12],
+      batches: [GrainBatch::new(sample_rate); 3],
       gain: 1.,
     }
   }
 
   pub fn reset(&mut self) {
-    self.grains.iter_mut().for_each(|grain| grain.reset());
+    self.batches.iter_mut().for_each(|batch| batch.reset());
   }
 
   pub fn get_gain(&self) -> f32 {
@@ -25,46 +43,33 @@ impl Grains {
 
   pub fn process(
     &mut self,
-    delay_line: &DelayLine,
+    delay_line: &DelayLineReader,
+    parameters: &GrainParameters,
     trigger: bool,
-    scan: f32,
-    spray: f32,
-    stereo: f32,
-    time: f32,
     start_position_phase: f32,
-    phase_step_size: f64,
     speed: f64,
-    is_reversed: bool,
-    window_factor: f64,
-    fade_factor: f64,
-    fade_offset: f64,
   ) -> (f32, f32) {
     if trigger {
-      let inactive_grain = self.grains.iter_mut().find(|grain| !grain.is_active());
-      match inactive_grain {
-        Some(grain) => {
-          grain.set_parameters(scan, spray, stereo, time, start_position_phase, is_reversed)
-        }
-        _ => {}
+      let inactive_lane = self.batches.iter_mut().find_map(|batch| {
+        let lane = batch.get_inactive_lane()?;
+        Some((batch, lane))
+      });
+      if let Some((batch, lane)) = inactive_lane {
+        batch.set_parameters(lane, parameters, start_position_phase);
       }
     }
 
+    // grains are read at the playback speed, in either direction
+    let band_limit = delay_line.get_band_limit(speed);
     let (grains_left, grains_right, gain) = self
-      .grains
+      .batches
       .iter_mut()
-      .filter(|grain| grain.is_active())
+      .filter(|batch| batch.is_active())
       .fold(
         (0., 0., 0.),
-        |(left_output, right_output, acc_gain), grain| {
-          let (left_grain, right_grain, grain_gain) = grain.process(
-            delay_line,
-            time as f64,
-            phase_step_size,
-            speed,
-            window_factor,
-            fade_factor,
-            fade_offset,
-          );
+        |(left_output, right_output, acc_gain), batch| {
+          let (left_grain, right_grain, grain_gain) =
+            batch.process(delay_line, parameters, speed, band_limit);
           (
             left_output + left_grain,
             right_output + right_grain,
@@ -78,3 +83,223 @@ impl Grains {
     (grains_left, grains_right)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::{GrainParameters, Grains},
+    crate::{
+      assert_approximately_eq,
+      delay_line::{DelayLine, DelayLineReader, Interpolation},
+      shared::float_ext::FloatExt,
+    },
+  };
+
+  /// The scalar grain that the grain batches are compared with.
+  #[derive(Clone, Copy)]
+  struct Grain {
+    phase: f64,
+    position: f64,
+    gain: (f32, f32),
+    sample_factor: f64,
+    is_reversed: bool,
+    is_active: bool,
+  }
+
+  impl Grain {
+    fn new(sample_rate: f32) -> Self {
+      Self {
+        phase: 0.,
+        position: 0.,
+        gain: (0.5, 0.5),
+        sample_factor: 1000. / sample_rate as f64,
+        is_reversed: false,
+        is_active: false,
+      }
+    }
+
+    fn process(
+      &mut self,
+      delay_line: &DelayLine,
+      parameters: &GrainParameters,
+      speed: f64,
+    ) -> (f32, f32, f32) {
+      let GrainParameters {
+        phase_step_size,
+        window_factor,
+        fade_factor,
+        fade_offset,
+        ..
+      } = *parameters;
+      let time = parameters.time as f64;
+      let speed = (if self.is_reversed {
+        1. + speed
+      } else {
+        1. - speed
+      }) * 0.5;
+      let position_a = Self::wrap(self.position) * 2.;
+      let position_b = Self::wrap(self.position + 0.5) * 2.;
+      let position_a_fade = Self::get_playhead_fade(position_a, fade_factor, fade_offset);
+      let position_b_fade = 1. - position_a_fade;
+      let grain_fade = self.get_grain_fade(window_factor);
+      let next_phase = self.phase + phase_step_size;
+      if next_phase < 1. {
+        self.phase = next_phase;
+      } else {
+        self.is_active = false;
+      }
+
+      self.position += self.sample_factor / time * speed;
+      let delay_out = Self::read_from_delay(
+        delay_line,
+        time,
+        position_a,
+        position_b,
+        grain_fade,
+        position_a_fade,
+        position_b_fade,
+      );
+      (delay_out * self.gain.0, delay_out * self.gain.1, grain_fade)
+    }
+
+    fn set_parameters(&mut self, parameters: &GrainParameters, start_position_phase: f32) {
+      let GrainParameters {
+        scan,
+        spray,
+        stereo,
+        time,
+        is_reversed,
+        ..
+      } = *parameters;
+      let spray = fastrand::f32() * spray / time;
+
+      self.phase = 0.;
+      self.position = (1. - (scan + spray + start_position_phase).fract() * 0.5) as f64;
+      self.is_active = true;
+      self.is_reversed = is_reversed;
+      self.set_panning(stereo);
+    }
+
+    fn read_from_delay(
+      delay_line: &DelayLine,
+      time: f64,
+      position_a: f64,
+      position_b: f64,
+      grain_fade: f32,
+      position_a_fade: f32,
+      position_b_fade: f32,
+    ) -> f32 {
+      let time_a = (position_a * time) as f32;
+      let time_b = (position_b * time) as f32;
+
+      delay_line
+          .read(time_a, Interpolation::Linear)
+           * position_a_fade.min(grain_fade) // take the minimum of both fades to prevent audible decreasing gain
+           + delay_line
+              .read(time_b, Interpolation::Linear)
+              * position_b_fade.min(grain_fade)
+    }
+
+    fn is_active(&self) -> bool {
+      self.is_active
+    }
+
+    fn get_grain_fade(&self, window_factor: f64) -> f32 {
+      let fade_in = (self.phase * window_factor).min(1.);
+      let fade_out = ((1. - self.phase) * window_factor).min(1.);
+      let fade = fade_in * fade_out;
+      fade.cubic_spline_curve() as f32
+    }
+
+    fn get_playhead_fade(position: f64, fade_factor: f64, fade_offset: f64) -> f32 {
+      let fade =
+        (position * fade_factor).min(1.) * ((fade_offset - position) * fade_factor).clamp(0., 1.);
+      fade.cubic_spline_curve() as f32
+    }
+
+    fn wrap(x: f64) -> f64 {
+      x - x.floor()
+    }
+
+    fn set_panning(&mut self, stereo: f32) {
+      if stereo == 0. {
+        self.gain = (0.5, 0.5);
+        return;
+      }
+      if stereo == 1. {
+        self.gain = if fastrand::bool() { (1., 0.) } else { (0., 1.) };
+        return;
+      }
+
+      if stereo > 0.8 {
+        let stereo_factor = (stereo - 0.8) * 2.5;
+        let hard_panning = if fastrand::bool() { 1. } else { 0. };
+        let random_panning = (fastrand::f32() - 0.5) + 0.5;
+        let panning = random_panning.mix(hard_panning, stereo_factor);
+        self.gain = (panning, 1. - panning)
+      } else {
+        let stereo_factor = stereo * 1.25;
+        let panning = (fastrand::f32() - 0.5) * stereo_factor + 0.5;
+        self.gain = (panning, 1. - panning);
+      }
+    }
+  }
+
+  #[test]
+  fn should_match_scalar_grains() {
+    let sample_rate = 44100.;
+    let time = 200.;
+    let mut delay_line = DelayLine::new(sample_rate as usize, sample_rate);
+    for _ in 0..sample_rate as usize {
+      delay_line.write(fastrand::f32() * 2. - 1.);
+    }
+    let mut grains = Grains::new(sample_rate);
+    let mut scalar_grains = [Grain::new(sample_rate); 12];
+
+    for i in 0..20000 {
+      let trigger = i % 300 == 0;
+      let is_reversed = i / 5000 % 2 == 1;
+      let speed = (i as f64 * 0.0005).sin() * 2.;
+      let parameters = GrainParameters {
+        scan: 0.2,
+        spray: 0.3,
+        stereo: 0.6,
+        time,
+        is_reversed,
+        phase_step_size: 0.0005,
+        window_factor: 2.5,
+        fade_factor: 2.,
+        fade_offset: 3.,
+        interpolation: Interpolation::Linear,
+      };
+      let start_position_phase = 0.1;
+
+      fastrand::seed(i);
+      let (left, right) = grains.process(
+        &DelayLineReader::new(&delay_line, None),
+        &parameters,
+        trigger,
+        start_position_phase,
+        speed,
+      );
+
+      fastrand::seed(i);
+      if trigger {
+        if let Some(grain) = scalar_grains.iter_mut().find(|grain| !grain.is_active()) {
+          grain.set_parameters(&parameters, start_position_phase);
+        }
+      }
+      let (expected_left, expected_right, expected_gain) = scalar_grains
+        .iter_mut()
+        .filter(|grain| grain.is_active())
+        .fold((0., 0., 0.), |(left, right, gain), grain| {
+          let out = grain.process(&delay_line, &parameters, speed);
+          (left + out.0, right + out.1, gain + out.2)
+        });
+
+      assert_approximately_eq!(left, expected_left, 5);
+      assert_approximately_eq!(right, expected_right, 5);
+      assert_approximately_eq!(grains.get_gain(), expected_gain, 5);
+    }
+  }
+}
@@ -0,0 +1,174 @@
+use {
+  super::GrainParameters,
+  crate::{
+    delay_line::{BandLimit, DelayLineReader},
+    shared::float_ext::FloatExt,
+  },
+  wide::f64x4,
+};
+
+pub const LANES: usize = 4;
+
+/// Four grains stored as struct-of-arrays, so their phases, positions and fades are computed at once.
+#[derive(Clone, Copy)]
+pub struct GrainBatch {
+  phase: f64x4,
+  position: f64x4,
+  direction: f64x4,
+  is_active: f64x4,
+  gain_left: [f32; LANES],
+  gain_right: [f32; LANES],
+  sample_factor: f64,
+}
+
+impl GrainBatch {
+  pub fn new(sample_rate: f32) -> Self {
+    Self {
+      phase: f64x4::ZERO,
+      position: f64x4::ZERO,
+      direction: f64x4::ONE,
+      is_active: f64x4::ZERO,
+      gain_left: [0.5; LANES],
+      gain_right: [0.5; LANES],
+      sample_factor: 1000. / sample_rate as f64,
+    }
+  }
+
+  pub fn process(
+    &mut self,
+    delay_line: &DelayLineReader,
+    parameters: &GrainParameters,
+    speed: f64,
+    band_limit: BandLimit,
+  ) -> (f32, f32, f32) {
+    let GrainParameters {
+      phase_step_size,
+      window_factor,
+      fade_factor,
+      fade_offset,
+      interpolation,
+      ..
+    } = *parameters;
+    let time = parameters.time as f64;
+    let is_active = self.is_active.to_array();
+    let speed = (f64x4::ONE - self.direction * speed) * 0.5;
+    let position_a = Self::wrap(self.position) * 2.;
+    let position_b = Self::wrap(self.position + 0.5) * 2.;
+    let position_a_fade = Self::get_playhead_fade(position_a, fade_factor, fade_offset);
+    let position_b_fade = f64x4::ONE - position_a_fade;
+    let grain_fade = self.get_grain_fade(window_factor);
+    let next_phase = self.phase + phase_step_size;
+    let has_next_phase = next_phase.simd_lt(f64x4::ONE);
+    self.phase = has_next_phase.select(next_phase, self.phase);
+    self.is_active = has_next_phase.select(self.is_active, f64x4::ZERO);
+    self.position += speed * (self.sample_factor / time);
+
+    // take the minimum of both fades to prevent audible decreasing gain
+    let fade_a = position_a_fade.min(grain_fade).to_array();
+    let fade_b = position_b_fade.min(grain_fade).to_array();
+    let time_a = (position_a * time).to_array();
+    let time_b = (position_b * time).to_array();
+    let grain_fade = grain_fade.to_array();
+
+    (0..LANES).filter(|&lane| is_active[lane] == 1.).fold(
+      (0., 0., 0.),
+      |(left_output, right_output, acc_gain), lane| {
+        let delay_out =
+          delay_line.read_band_limited(time_a[lane] as f32, interpolation, band_limit)
+            * fade_a[lane] as f32
+            + delay_line.read_band_limited(time_b[lane] as f32, interpolation, band_limit)
+              * fade_b[lane] as f32;
+        (
+          left_output + delay_out * self.gain_left[lane],
+          right_output + delay_out * self.gain_right[lane],
+          acc_gain + grain_fade[lane] as f32,
+        )
+      },
+    )
+  }
+
+  pub fn reset(&mut self) {
+    self.phase = f64x4::ZERO;
+    self.position = f64x4::ZERO;
+    self.is_active = f64x4::ZERO;
+  }
+
+  pub fn set_parameters(
+    &mut self,
+    lane: usize,
+    parameters: &GrainParameters,
+    start_position_phase: f32,
+  ) {
+    let GrainParameters {
+      scan,
+      spray,
+      stereo,
+      time,
+      is_reversed,
+      ..
+    } = *parameters;
+    let spray = fastrand::f32() * spray / time;
+    let (gain_left, gain_right) = Self::get_panning(stereo);
+
+    self.phase.as_mut_array()[lane] = 0.;
+    self.position.as_mut_array()[lane] =
+      (1. - (scan + spray + start_position_phase).fract() * 0.5) as f64;
+    self.direction.as_mut_array()[lane] = if is_reversed { -1. } else { 1. };
+    self.is_active.as_mut_array()[lane] = 1.;
+    self.gain_left[lane] = gain_left;
+    self.gain_right[lane] = gain_right;
+  }
+
+  pub fn is_active(&self) -> bool {
+    self.is_active.reduce_add() > 0.
+  }
+
+  pub fn get_inactive_lane(&self) -> Option<usize> {
+    self
+      .is_active
+      .to_array()
+      .iter()
+      .position(|is_active| *is_active == 0.)
+  }
+
+  fn get_grain_fade(&self, window_factor: f64) -> f64x4 {
+    let fade_in = (self.phase * window_factor).min(f64x4::ONE);
+    let fade_out = ((f64x4::ONE - self.phase) * window_factor).min(f64x4::ONE);
+    Self::cubic_spline_curve(fade_in * fade_out)
+  }
+
+  fn get_playhead_fade(position: f64x4, fade_factor: f64, fade_offset: f64) -> f64x4 {
+    let fade = (position * fade_factor).min(f64x4::ONE)
+      * ((f64x4::splat(fade_offset) - position) * fade_factor).clamp(f64x4::ZERO, f64x4::ONE);
+    Self::cubic_spline_curve(fade)
+  }
+
+  fn cubic_spline_curve(x: f64x4) -> f64x4 {
+    x * x * (f64x4::splat(3.) - x * 2.)
+  }
+
+  fn wrap(x: f64x4) -> f64x4 {
+    x - x.floor()
+  }
+
+  fn get_panning(stereo: f32) -> (f32, f32) {
+    if stereo == 0. {
+      return (0.5, 0.5);
+    }
+    if stereo == 1. {
+      return if fastrand::bool() { (1., 0.) } else { (0., 1.) };
+    }
+
+    if stereo > 0.8 {
+      let stereo_factor = (stereo - 0.8) * 2.5;
+      let hard_panning = if fastrand::bool() { 1. } else { 0. };
+      let random_panning = (fastrand::f32() - 0.5) + 0.5;
+      let panning = random_panning.mix(hard_panning, stereo_factor);
+      (panning, 1. - panning)
+    } else {
+      let stereo_factor = stereo * 1.25;
+      let panning = (fastrand::f32() - 0.5) * stereo_factor + 0.5;
+      (panning, 1. - panning)
+    }
+  }
+}
@@ -0,0 +1,111 @@
+use {
+  super::{
+    grain_trigger::GrainTrigger,
+    grains::{GrainParameters, Grains},
+  },
+  crate::{
+    delay_line::{DelayLine, DelayLineReader, Interpolation},
+    shared::float_ext::FloatExt,
+    FADE_TIME,
+  },
+};
+
+const GRAIN_DURATION: f64 = 100.;
+const DENSITY: f64 = 2.;
+
+/// Pitch shifts the delay output with a dedicated set of grains, which are read at the delay time.
+pub struct Shimmer {
+  grains: Grains,
+  grain_trigger: GrainTrigger,
+  sample_rate: f64,
+}
+
+impl Shimmer {
+  pub fn new(sample_rate: f32) -> Self {
+    Self {
+      grains: Grains::new(sample_rate),
+      grain_trigger: GrainTrigger::new(sample_rate),
+      sample_rate: sample_rate as f64,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.grains.reset();
+    self.grain_trigger.reset();
+  }
+
+  pub fn process(
+    &mut self,
+    delay_line: &DelayLine,
+    time: f32,
+    speed: f64,
+    interpolation: Interpolation,
+  ) -> f32 {
+    let grain_duration = GRAIN_DURATION.min(time as f64);
+    let phase_step_size = grain_duration.mstosamps(self.sample_rate).recip();
+    let fade_factor = time as f64 / FADE_TIME;
+    let trigger = self
+      .grain_trigger
+      .process(grain_duration, DENSITY, false, false);
+    let parameters = GrainParameters {
+      scan: 0.,
+      spray: 0.,
+      stereo: 0.,
+      time,
+      is_reversed: false,
+      phase_step_size,
+      window_factor: DENSITY,
+      fade_factor,
+      fade_offset: fade_factor.recip() + 1.,
+      interpolation,
+    };
+    let (left, right) = self.grains.process(
+      &DelayLineReader::new(delay_line, None),
+      &parameters,
+      trigger,
+      0.,
+      speed,
+    );
+    let gain = self.grains.get_gain();
+    if gain == 0. {
+      0.
+    } else {
+      (left + right) * gain.recip().sqrt()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Shimmer;
+  use crate::delay_line::{DelayLine, Interpolation};
+  use std::f32::consts::TAU;
+
+  fn count_zero_crossings(signal: &[f32]) -> usize {
+    signal
+      .windows(2)
+      .filter(|pair| pair[0] < 0. && pair[1] >= 0.)
+      .count()
+  }
+
+  #[test]
+  fn should_shift_the_pitch_up_an_octave() {
+    let sample_rate = 44100.;
+    let mut delay_line = DelayLine::new(44100, sample_rate);
+    let mut shimmer = Shimmer::new(sample_rate);
+    let input: Vec<f32> = (0..44100)
+      .map(|i| (i as f32 * 100. * TAU / sample_rate).sin())
+      .collect();
+    let output: Vec<f32> = input
+      .iter()
+      .map(|x| {
+        let out = shimmer.process(&delay_line, 200., 2., Interpolation::Linear);
+        delay_line.write(*x);
+        out
+      })
+      .skip(22050)
+      .collect();
+    let crossings = count_zero_crossings(&output);
+    assert!((95..=105).contains(&crossings));
+  }
+}
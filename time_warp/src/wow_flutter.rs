@@ -0,0 +1,51 @@
+use {crate::shared::phasor::Phasor, std::f64::consts::TAU};
+
+const WOW_FREQUENCY: f64 = 0.6;
+const FLUTTER_FREQUENCY: f64 = 7.;
+const MAX_WOW_TIME: f32 = 2.;
+const MAX_FLUTTER_TIME: f32 = 0.15;
+
+pub struct WowFlutter {
+  wow_phasor: Phasor,
+  flutter_phasor: Phasor,
+  wow_frequency: f64,
+  prev_wow_phase: f64,
+}
+
+impl WowFlutter {
+  pub fn new(sample_rate: f32) -> Self {
+    Self {
+      wow_phasor: Phasor::new(sample_rate as f64),
+      flutter_phasor: Phasor::new(sample_rate as f64),
+      wow_frequency: WOW_FREQUENCY,
+      prev_wow_phase: 0.,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.wow_phasor.reset();
+    self.flutter_phasor.reset();
+    self.prev_wow_phase = 0.;
+  }
+
+  /// Returns the time in milliseconds to add to the read time of the delay line.
+  pub fn process(&mut self, wow: f32, flutter: f32) -> f32 {
+    if wow == 0. && flutter == 0. {
+      return 0.;
+    }
+    let wow_phase = self.wow_phasor.process(self.wow_frequency);
+    if wow_phase < self.prev_wow_phase {
+      // vary the wow rate per cycle, like an irregular tape transport
+      self.wow_frequency = WOW_FREQUENCY * (0.75 + fastrand::f64() * 0.5);
+    }
+    self.prev_wow_phase = wow_phase;
+    let flutter_phase = self.flutter_phasor.process(FLUTTER_FREQUENCY);
+
+    Self::get_unipolar_sine(wow_phase) * wow * MAX_WOW_TIME
+      + Self::get_unipolar_sine(flutter_phase) * flutter * MAX_FLUTTER_TIME
+  }
+
+  fn get_unipolar_sine(phase: f64) -> f32 {
+    (0.5 - (phase * TAU).cos() * 0.5) as f32
+  }
+}